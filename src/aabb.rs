@@ -3,6 +3,7 @@ use ::std::mem::swap;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone)]
 pub struct AABB {
     min: Vec3,
@@ -22,6 +23,15 @@ impl AABB {
         self.max
     }
 
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
     pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
         for a in 0..3 {
             let inv_direction = ray.direction()[a].recip();
@@ -30,8 +40,8 @@ impl AABB {
             if inv_direction < 0.0 {
                 swap(&mut t0, &mut t1);
             }
-            t_min = t0.min(t_min);
-            t_max = t1.max(t_max);
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
             if t_max <= t_min {
                 return false;
             }
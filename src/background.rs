@@ -0,0 +1,42 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Light coming from rays which hit nothing
+#[derive(Debug, Clone)]
+pub enum Background {
+    /// Black, the scene is only lit by its emissive materials
+    None,
+    Solid(Vec3),
+    /// Blend from `bottom` when looking down to `top` when looking up
+    Gradient {
+        bottom: Vec3,
+        top: Vec3,
+    },
+}
+
+impl Default for Background {
+    /// The blue sky of the books
+    fn default() -> Self {
+        Background::Gradient {
+            bottom: Self::SKY_BOTTOM,
+            top: Self::SKY_TOP,
+        }
+    }
+}
+
+impl Background {
+    pub const SKY_BOTTOM: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+    pub const SKY_TOP: Vec3 = Vec3::new(0.5, 0.7, 1.0);
+
+    pub fn color(&self, ray: &Ray) -> Vec3 {
+        match self {
+            Background::None => Vec3::default(),
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let unit_direction = ray.direction().unit_vector();
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+        }
+    }
+}
@@ -1,100 +1,451 @@
-use ::std::sync::Arc;
+use ::std::mem::swap;
 
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
-use crate::random::random;
 use crate::ray::Ray;
+use crate::vec3::Vec3;
 
-#[derive(Debug)]
-struct BVHNode {
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+/// Number of buckets the centroids are sorted in when looking for the best split
+const NB_BINS: usize = 12;
+/// A node with more primitives than this is always split
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of testing a node against a ray, relative to the cost of testing a primitive
+const TRAVERSAL_COST: f32 = 0.125;
+/// Beyond this depth, nodes are split in the middle so the traversal stack cannot overflow
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
     aabb: AABB,
+    /// For a leaf, index of its first primitive, otherwise index of its right child,
+    /// the left child being always the next node
+    offset: u32,
+    /// Number of primitives of a leaf, 0 for an interior node
+    count: u16,
+    /// Axis along which the children of an interior node were split
+    axis: u8,
+}
+
+/// Bounding volume hierarchy built with the surface area heuristic, stored as a flat array
+/// of nodes in depth-first order
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct BVH<T> {
+    nodes: Vec<Node>,
+    /// Sorted so every leaf owns a contiguous range
+    primitives: Vec<T>,
 }
 
-impl BVHNode {
-    pub fn new(hittables: &mut [Arc<dyn Hittable>], initial_time: f32, final_time: f32) -> Self {
-        let axis = (3.0 * random()) as i32;
+impl<T: Hittable> BVH<T> {
+    /// Every primitive must have a bounding box over `[initial_time, final_time]`
+    pub fn new(primitives: Vec<T>, initial_time: f32, final_time: f32) -> Self {
+        let mut infos = primitives
+            .iter()
+            .enumerate()
+            .map(|(index, primitive)| {
+                let aabb = primitive
+                    .bounding_box(initial_time, final_time)
+                    .expect("No bounding box in BVH constructor");
+                (aabb, aabb.centroid(), index)
+            })
+            .collect::<Vec<_>>();
+
+        let mut nodes = Vec::with_capacity(2 * infos.len());
+        if !infos.is_empty() {
+            Self::build(&mut infos, 0, 0, &mut nodes);
+        }
+
+        let mut primitives = primitives.into_iter().map(Some).collect::<Vec<_>>();
+        let primitives = infos
+            .iter()
+            .map(|(_, _, index)| primitives[*index].take().unwrap())
+            .collect();
+
+        Self { nodes, primitives }
+    }
 
-        let getter = |hittable: &Arc<dyn Hittable>| {
-            hittable
-                .bounding_box(0.0, 0.0)
-                .expect("Non bounding box in BVHNode constructor")
-                .min()
+    /// Push the node covering `infos` and its descendants, return its index
+    fn build(
+        infos: &mut [(AABB, Vec3, usize)],
+        offset: usize,
+        depth: usize,
+        nodes: &mut Vec<Node>,
+    ) -> usize {
+        let index = nodes.len();
+        let count = infos.len();
+        let aabb = infos
+            .iter()
+            .skip(1)
+            .fold(infos[0].0, |aabb, info| AABB::surrounding_box(aabb, info.0));
+        let leaf = Node {
+            aabb,
+            offset: offset as u32,
+            count: count as u16,
+            axis: 0,
         };
+        nodes.push(leaf);
 
-        match axis {
-            0 => hittables.sort_unstable_by(|hit1, hit2| {
-                getter(hit1).x().partial_cmp(&getter(hit2).x()).unwrap()
-            }),
-            1 => hittables.sort_unstable_by(|hit1, hit2| {
-                getter(hit1).y().partial_cmp(&getter(hit2).y()).unwrap()
-            }),
-            _ => hittables.sort_unstable_by(|hit1, hit2| {
-                getter(hit1).z().partial_cmp(&getter(hit2).z()).unwrap()
-            }),
+        if count == 1 {
+            return index;
+        }
+
+        let (min, max) = infos.iter().skip(1).fold(
+            (infos[0].1, infos[0].1),
+            |(mut min, mut max), (_, centroid, _)| {
+                for a in 0..3 {
+                    min[a] = min[a].min(centroid[a]);
+                    max[a] = max[a].max(centroid[a]);
+                }
+                (min, max)
+            },
+        );
+        let extent = max - min;
+        let bin = |centroid: Vec3, axis: usize| {
+            let b = (NB_BINS as f32 * (centroid[axis] - min[axis]) / extent[axis]) as usize;
+            b.min(NB_BINS - 1)
+        };
+
+        // Best (cost, axis, first bin of the right child)
+        let mut best: Option<(f32, usize, usize)> = None;
+        if depth < MAX_SAH_DEPTH {
+            for axis in 0..3 {
+                if extent[axis] <= 0.0 {
+                    continue;
+                }
+
+                let mut bins: [(Option<AABB>, usize); NB_BINS] = [(None, 0); NB_BINS];
+                for (aabb, centroid, _) in infos.iter() {
+                    let (bin_aabb, bin_count) = &mut bins[bin(*centroid, axis)];
+                    *bin_aabb = Some(bin_aabb.map_or(*aabb, |b| AABB::surrounding_box(b, *aabb)));
+                    *bin_count += 1;
+                }
+
+                // Area and count of everything on the right of each split, swept backwards
+                let mut right = [(0.0, 0); NB_BINS];
+                let mut accumulated: (Option<AABB>, usize) = (None, 0);
+                for split in (1..NB_BINS).rev() {
+                    accumulated = merge(accumulated, bins[split]);
+                    right[split] = (
+                        accumulated.0.map_or(0.0, |b| b.surface_area()),
+                        accumulated.1,
+                    );
+                }
+
+                let mut accumulated = (None, 0);
+                for split in 1..NB_BINS {
+                    accumulated = merge(accumulated, bins[split - 1]);
+                    let (left_area, left_count) = (
+                        accumulated.0.map_or(0.0, |b| b.surface_area()),
+                        accumulated.1,
+                    );
+                    let (right_area, right_count) = right[split];
+                    if left_count == 0 || right_count == 0 {
+                        continue;
+                    }
+
+                    let cost = TRAVERSAL_COST
+                        + (left_area * left_count as f32 + right_area * right_count as f32)
+                            / aabb.surface_area().max(f32::MIN_POSITIVE);
+                    if best.is_none_or(|(best_cost, ..)| cost < best_cost) {
+                        best = Some((cost, axis, split));
+                    }
+                }
+            }
         }
 
-        let len = hittables.len();
-
-        let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>) = match len {
-            1 => (hittables[0].clone(), hittables[0].clone()),
-            2 => (hittables[0].clone(), hittables[1].clone()),
-            _ => (
-                Arc::new(Self::new(
-                    &mut hittables[..(len / 2)],
-                    initial_time,
-                    final_time,
-                )),
-                Arc::new(Self::new(
-                    &mut hittables[(len / 2)..],
-                    initial_time,
-                    final_time,
-                )),
-            ),
+        let (axis, mid) = match best {
+            Some((cost, _, _)) if cost >= count as f32 && count <= MAX_LEAF_SIZE => return index,
+            Some((_, axis, split)) => {
+                let mut mid = 0;
+                for i in 0..count {
+                    if bin(infos[i].1, axis) < split {
+                        infos.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+                (axis, mid)
+            }
+            // The centroids are all at the same place (or the tree is too deep)
+            None if count <= MAX_LEAF_SIZE => return index,
+            None => {
+                let axis = (0..3)
+                    .max_by(|a, b| extent[*a].partial_cmp(&extent[*b]).unwrap())
+                    .unwrap();
+                infos.sort_unstable_by(|a, b| a.1[axis].partial_cmp(&b.1[axis]).unwrap());
+                (axis, count / 2)
+            }
         };
 
-        let box_left = left.bounding_box(initial_time, final_time);
-        let box_right = right.bounding_box(initial_time, final_time);
+        Self::build(&mut infos[..mid], offset, depth + 1, nodes);
+        let right = Self::build(&mut infos[mid..], offset + mid, depth + 1, nodes);
+        nodes[index] = Node {
+            aabb,
+            offset: right as u32,
+            count: 0,
+            axis: axis as u8,
+        };
+
+        index
+    }
+}
 
-        if box_left.is_none() || box_right.is_none() {
-            panic!("No bounding box in BVHNode constructor");
+impl<T> BVH<T> {
+    /// Call `visit` on every primitive of the leaves whose boxes the ray goes through between
+    /// `t_min` and `t_max`, which includes every primitive it hits there
+    pub fn for_each_candidate<F: FnMut(&T)>(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut visit: F,
+    ) {
+        if self.nodes.is_empty() {
+            return;
         }
 
-        let aabb = AABB::surrounding_box(box_left.unwrap(), box_right.unwrap());
+        let mut stack = [0u32; STACK_SIZE];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let index = stack[len] as usize;
+            let node = &self.nodes[index];
+            if !node.aabb.hit(ray, t_min, t_max) {
+                continue;
+            }
 
-        Self { left, right, aabb }
+            if node.count > 0 {
+                let start = node.offset as usize;
+                self.primitives[start..(start + node.count as usize)]
+                    .iter()
+                    .for_each(&mut visit);
+            } else {
+                stack[len] = node.offset;
+                stack[len + 1] = index as u32 + 1;
+                len += 2;
+            }
+        }
     }
 }
 
-impl Hittable for BVHNode {
+fn merge(acc: (Option<AABB>, usize), bin: (Option<AABB>, usize)) -> (Option<AABB>, usize) {
+    let aabb = match (acc.0, bin.0) {
+        (Some(a), Some(b)) => Some(AABB::surrounding_box(a, b)),
+        (a, b) => a.or(b),
+    };
+    (aabb, acc.1 + bin.1)
+}
+
+impl<T: Hittable> Hittable for BVH<T> {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        if self.aabb.hit(ray, t_min, t_max) {
-            let hit_left = self.left.hit(ray, t_min, t_max);
-            let hit_right = self.right.hit(ray, t_min, t_max);
-
-            if hit_left.is_some() && hit_right.is_some() {
-                let rec_left = hit_left.unwrap();
-                let rec_right = hit_right.unwrap();
-                let rec = if rec_left.t < rec_right.t {
-                    rec_left
-                } else {
-                    rec_right
-                };
-                Some(rec)
-            } else if hit_left.is_some() {
-                hit_left
-            } else if hit_right.is_some() {
-                hit_right
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut len = 1;
+        let mut hit_record = None;
+        let mut closest_so_far = t_max;
+
+        while len > 0 {
+            len -= 1;
+            let index = stack[len] as usize;
+            let node = &self.nodes[index];
+            if !node.aabb.hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+
+            if node.count > 0 {
+                let start = node.offset as usize;
+                for primitive in &self.primitives[start..(start + node.count as usize)] {
+                    if let Some(rec) = primitive.hit(ray, t_min, closest_so_far) {
+                        closest_so_far = rec.t;
+                        hit_record = Some(rec);
+                    }
+                }
             } else {
-                None
+                // Push the farthest child first so the nearest one is visited first
+                let (mut near, mut far) = (index as u32 + 1, node.offset);
+                if ray.direction()[node.axis as usize] < 0.0 {
+                    swap(&mut near, &mut far);
+                }
+                stack[len] = far;
+                stack[len + 1] = near;
+                len += 2;
             }
-        } else {
-            None
         }
+
+        hit_record
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        Some(self.aabb)
+        self.nodes.first().map(|node| node.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::sync::Arc;
+
+    use super::*;
+    use crate::hittable::Scene;
+    use crate::material::{Lambertian, Material};
+    use crate::moving_sphere::MovingSphere;
+    use crate::random::{Pcg32, Rng};
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    fn material(rng: &mut Pcg32) -> Arc<dyn Material> {
+        let color = Vec3::new(rng.linear(), rng.linear(), rng.linear());
+        Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(color))))
+    }
+
+    fn point(rng: &mut Pcg32, size: f32) -> Vec3 {
+        Vec3::new(rng.linear() - 0.5, rng.linear() - 0.5, rng.linear() - 0.5) * size
+    }
+
+    fn assert_same_vec(a: Vec3, b: Vec3) {
+        assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+    }
+
+    /// Fire random rays from around the scene, with random times, and check that the BVH
+    /// finds exactly what testing every hittable finds
+    fn assert_same_hits(hittables: Vec<Arc<dyn Hittable>>, rng: &mut Pcg32) {
+        let brute_force = Scene::new(hittables.clone());
+        let mut scene = Scene::new(hittables);
+        scene.build_bvh(0.0, 1.0);
+
+        let mut nb_hits = 0;
+        for _ in 0..5000 {
+            let origin = point(rng, 40.0);
+            let target = point(rng, 10.0);
+            let ray = Ray::new(origin, target - origin, rng.linear());
+            let expected = brute_force.hit_brute_force(&ray, 0.001, f32::MAX);
+            let found = scene.hit(&ray, 0.001, f32::MAX);
+            match (found, expected) {
+                (Some(found), Some(expected)) => {
+                    assert_eq!(found.t, expected.t);
+                    assert_same_vec(found.p, expected.p);
+                    assert!(Arc::ptr_eq(&found.material, &expected.material));
+                    nb_hits += 1;
+                }
+                (None, None) => {}
+                (found, expected) => panic!(
+                    "BVH hit {:?} but brute force hit {:?}",
+                    found.map(|rec| rec.t),
+                    expected.map(|rec| rec.t)
+                ),
+            }
+        }
+        assert!(nb_hits > 0);
+    }
+
+    #[test]
+    fn same_hits_as_brute_force() {
+        let mut rng = Pcg32::new(1, 2);
+        let mut hittables: Vec<Arc<dyn Hittable>> = Vec::new();
+        for _ in 0..300 {
+            let center = point(&mut rng, 20.0);
+            let radius = 0.05 + rng.linear();
+            hittables.push(Arc::new(Sphere::new(center, radius, material(&mut rng))));
+        }
+        for _ in 0..100 {
+            let center = point(&mut rng, 20.0);
+            let moved = center + point(&mut rng, 2.0);
+            let radius = 0.05 + rng.linear();
+            let sphere = MovingSphere::new(center, moved, 0.0, 1.0, radius, material(&mut rng));
+            hittables.push(Arc::new(sphere));
+        }
+
+        assert_same_hits(hittables, &mut rng);
+    }
+
+    #[test]
+    fn same_hits_with_coincident_centroids() {
+        let mut rng = Pcg32::new(3, 4);
+        let mut hittables: Vec<Arc<dyn Hittable>> = Vec::new();
+        // Far more than a leaf can hold, all centered on the same point
+        for i in 0..(4 * MAX_LEAF_SIZE + 1) {
+            let radius = 0.5 + 0.25 * i as f32;
+            hittables.push(Arc::new(Sphere::new(
+                Vec3::new(1.0, 2.0, 3.0),
+                radius,
+                material(&mut rng),
+            )));
+        }
+        // A few groups sharing their centroids with different boxes
+        for _ in 0..10 {
+            let center = point(&mut rng, 20.0);
+            for i in 0..3 {
+                let radius = 0.2 + 0.3 * i as f32;
+                hittables.push(Arc::new(Sphere::new(center, radius, material(&mut rng))));
+            }
+        }
+
+        assert_same_hits(hittables, &mut rng);
+    }
+
+    #[test]
+    fn single_point_larger_than_a_leaf() {
+        let mut rng = Pcg32::new(5, 6);
+        let hittables: Vec<Arc<dyn Hittable>> = (0..(MAX_LEAF_SIZE + 1))
+            .map(|i| {
+                let radius = 1.0 + i as f32;
+                Arc::new(Sphere::new(Vec3::default(), radius, material(&mut rng)))
+                    as Arc<dyn Hittable>
+            })
+            .collect();
+
+        assert_same_hits(hittables, &mut rng);
+    }
+
+    #[test]
+    fn aabb_hit_narrows_the_interval() {
+        let aabb = AABB::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+
+        // Crosses the slabs of x for t in [1, 2] and of y for t in [3, 4], never both
+        let ray = Ray::new(Vec3::new(-1.0, -3.0, 0.5), Vec3::new(1.0, 1.0, 0.0), 0.0);
+        assert!(!aabb.hit(&ray, 0.0, f32::MAX));
+
+        // Through the box for t in [1, 2]
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(aabb.hit(&ray, 0.0, f32::MAX));
+        assert!(aabb.hit(&ray, 1.5, 1.6));
+        assert!(!aabb.hit(&ray, 0.0, 0.5));
+        assert!(!aabb.hit(&ray, 2.5, f32::MAX));
+
+        // Going away from the box
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(!aabb.hit(&ray, 0.0, f32::MAX));
+    }
+
+    #[test]
+    fn sphere_bounding_box() {
+        let mut rng = Pcg32::new(7, 8);
+        let sphere = Sphere::new(Vec3::new(1.0, 2.0, 3.0), 0.5, material(&mut rng));
+        let aabb = sphere.bounding_box(0.0, 1.0).unwrap();
+        assert_same_vec(aabb.min(), Vec3::new(0.5, 1.5, 2.5));
+        assert_same_vec(aabb.max(), Vec3::new(1.5, 2.5, 3.5));
+    }
+
+    #[test]
+    fn moving_sphere_bounding_box() {
+        let mut rng = Pcg32::new(9, 10);
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 4.0, 0.0),
+            Vec3::new(2.0, 0.0, 1.0),
+            0.0,
+            1.0,
+            0.5,
+            material(&mut rng),
+        );
+        let aabb = sphere.bounding_box(0.0, 1.0).unwrap();
+        assert_same_vec(aabb.min(), Vec3::new(-0.5, -0.5, -0.5));
+        assert_same_vec(aabb.max(), Vec3::new(2.5, 4.5, 1.5));
+
+        // Only the part of the motion in the interval
+        let aabb = sphere.bounding_box(0.0, 0.5).unwrap();
+        assert_same_vec(aabb.min(), Vec3::new(-0.5, 1.5, -0.5));
+        assert_same_vec(aabb.max(), Vec3::new(1.5, 4.5, 1.0));
     }
 }
@@ -1,5 +1,5 @@
-use crate::random::random;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 #[derive(Debug)]
@@ -10,13 +10,57 @@ pub struct Camera {
     origin: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f32,
     initial_time: f32,
     final_time: f32,
 }
 
+/// Parameters of a `Camera`, except the aspect ratio which is given by the size of the image
+#[derive(Debug, Clone)]
+pub struct CameraSettings {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub v_up: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub initial_time: f32,
+    pub final_time: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            look_from: Vec3::new(13.0, 2.0, 3.0),
+            look_at: Vec3::new(0.0, 0.0, 0.0),
+            v_up: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            initial_time: 0.0,
+            final_time: 1.0,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect: f32) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.v_up,
+            self.vfov,
+            aspect,
+            self.aperture,
+            self.focus_dist,
+            self.initial_time,
+            self.final_time,
+        )
+    }
+}
+
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -51,17 +95,16 @@ impl Camera {
             origin,
             u,
             v,
-            w,
             lens_radius,
             initial_time,
             final_time,
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rand_vec = Vec3::random_in_unit_disk() * self.lens_radius;
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rand_vec = Vec3::random_in_unit_disk(sampler) * self.lens_radius;
         let offset = self.u * rand_vec.x() + self.v * rand_vec.y();
-        let time = self.initial_time + random() * (self.final_time - self.initial_time);
+        let time = self.initial_time + sampler.get_1d() * (self.final_time - self.initial_time);
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin - offset,
@@ -1,106 +1,81 @@
-use std::sync::{Arc, Mutex};
-
-use super::{HEIGHT, MAX_DEPTH, RAY_PER_PIXEL, WIDTH};
+use std::sync::Arc;
 
 use crate::camera::Camera;
+use crate::config::Config;
+use crate::film::Film;
 use crate::hittable::Scene;
-use crate::random::random;
-use crate::ray::Ray;
-use crate::vec3::Vec3;
-
-pub fn color(ray: Ray, scene: &Scene, depth: usize) -> Vec3 {
-    if let Some(rec) = scene.hit(&ray, 0.001, std::f32::MAX) {
-        if let Some((attenuation, scattered)) = if depth < MAX_DEPTH {
-            rec.material.scatter(&ray, &rec)
-        } else {
-            None
-        } {
-            attenuation * color(scattered, &scene, depth + 1)
-        } else {
-            Default::default()
-        }
-    } else {
-        let unit_direction = ray.direction().unit_vector();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
-    }
-}
-
-pub fn from_rgb(r: f32, g: f32, b: f32) -> u32 {
-    let (r, g, b) = (r as u32, g as u32, b as u32);
-    (r << 16) | (g << 8) | b
-}
-
-#[allow(dead_code)]
-pub fn to_rgb(mut color: u32) -> (u8, u8, u8) {
-    let r = color >> 16;
-    color ^= r << 16;
-    let g = color >> 8;
-    color ^= g << 8;
-    let b = color;
-    (r as u8, g as u8, b as u8)
-}
+use crate::integrator::Integrator;
 
 pub struct Chunk {
     pub width: usize,
     pub height: usize,
     pub offset_x: usize,
     pub offset_y: usize,
-    pub buffer: Arc<Mutex<Vec<u32>>>,
     camera: Arc<Camera>,
     scene: Arc<Scene>,
+    integrator: Arc<dyn Integrator>,
+    config: Arc<Config>,
 }
 
 impl Chunk {
+    /// The chunk is cropped so it never goes past the borders of the image
     pub fn new(
-        width: usize,
-        height: usize,
         offset_x: usize,
         offset_y: usize,
-        buffer: Arc<Mutex<Vec<u32>>>,
         camera: Arc<Camera>,
         scene: Arc<Scene>,
+        integrator: Arc<dyn Integrator>,
+        config: Arc<Config>,
     ) -> Self {
         Self {
-            width,
-            height,
+            width: config.chunk_width.min(config.width - offset_x),
+            height: config.chunk_height.min(config.height - offset_y),
             offset_x,
             offset_y,
-            buffer,
             camera,
             scene,
+            integrator,
+            config,
         }
     }
 
-    pub fn process(self) {
-        for j in self.offset_y..(self.offset_y + self.height) {
-            for i in self.offset_x..(self.offset_x + self.width) {
-                let mut pixel_color = Vec3::default();
+    /// Render the chunk into a tile with a border as wide as the filter, since samples near
+    /// the edges also count in the pixels of the neighbour chunks. The tile is then to be
+    /// merged into the film.
+    pub fn process(self) -> Film {
+        let mut sampler = self.config.sampler.build(
+            self.config.seed,
+            self.config.ray_per_pixel,
+            self.config.generator,
+        );
+        let filter = self.config.filter();
 
-                for _ in 0..RAY_PER_PIXEL {
-                    let u = (i as f32 + random()) / WIDTH as f32;
-                    let v = ((HEIGHT - j) as f32 + random()) / HEIGHT as f32;
+        let border = filter.radius.ceil() as usize;
+        let x0 = self.offset_x.saturating_sub(border);
+        let y0 = self.offset_y.saturating_sub(border);
+        let x1 = (self.offset_x + self.width + border).min(self.config.width);
+        let y1 = (self.offset_y + self.height + border).min(self.config.height);
+        let mut tile = Film::tile(x0, y0, x1 - x0, y1 - y0);
 
-                    let ray = self.camera.get_ray(u, v);
-                    pixel_color += color(ray, &*self.scene, 0);
-                }
-                pixel_color /= RAY_PER_PIXEL as f32;
-                pixel_color = Vec3::new(
-                    pixel_color.x().sqrt(),
-                    pixel_color.y().sqrt(),
-                    pixel_color.z().sqrt(),
-                );
+        for j in self.offset_y..(self.offset_y + self.height) {
+            for i in self.offset_x..(self.offset_x + self.width) {
+                let pixel = (i + j * self.config.width) as u64;
+                for sample in 0..self.config.ray_per_pixel {
+                    sampler.start_sample(pixel, sample as u64);
+                    let (dx, dy) = sampler.get_2d();
+                    // Position on the film, y going downwards
+                    let (x, y) = (i as f32 + dx, j as f32 + dy);
+                    let u = x / self.config.width as f32;
+                    let v = 1.0 - y / self.config.height as f32;
 
-                let Vec3 { data: [r, g, b] } = pixel_color * 255.99;
-                if let Some(index) = self.buffer.lock().unwrap().get_mut(i + j * WIDTH) {
-                    *index = from_rgb(r, g, b);
-                } else {
-                    #[cfg(debug_assertions)]
-                    eprintln!("{}, {}, {}, {}", i, j, self.offset_x, self.offset_y);
+                    let ray = self.camera.get_ray(u, v, &mut *sampler);
+                    let color = self.integrator.radiance(ray, &self.scene, &mut *sampler);
+                    tile.add_sample(x, y, color, &filter);
                 }
             }
         }
         #[cfg(debug_assertions)]
         eprintln!("finished");
+        tile
     }
 }
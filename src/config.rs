@@ -0,0 +1,270 @@
+use ::std::env;
+use ::std::fmt;
+use ::std::path::PathBuf;
+use ::std::str::FromStr;
+use ::std::sync::Arc;
+use ::std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::camera::CameraSettings;
+use crate::filter::{Filter, FilterKind};
+use crate::integrator::{Depths, Integrator, IntegratorKind};
+use crate::output::{OutputError, OutputFormat};
+use crate::random::Generator;
+use crate::sampler::SamplerKind;
+use crate::tonemap::ToneMap;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+
+Options:
+    -w, --width <PIXELS>        Width of the image [default: 800]
+    -h, --height <PIXELS>       Height of the image [default: 800]
+    -s, --samples <N>           Rays per pixel [default: 100]
+    -d, --depth <N>             Maximum number of bounces of each kind, overriding the three following ones
+        --diffuse-depth <N>     Maximum number of diffuse bounces, scattering in volumes included [default: 32]
+        --specular-depth <N>    Maximum number of reflections on metal or glass [default: 128]
+        --transmission-depth <N>
+                                Maximum number of refractions through glass [default: 128]
+        --rr-depth <N>          Bounces before the paths carrying little light start being randomly ended [default: 4]
+        --chunk <W>x<H>         Size of the tiles given to the workers [default: 50x50]
+    -j, --workers <N>           Number of worker threads [default: 10]
+        --seed <N>              Seed of the random generators, the same seed giving the same image [default: current time]
+        --rng <NAME>            Random generator, xoshiro or pcg [default: xoshiro]
+        --sampler <NAME>        Sample pattern, independent, stratified, halton or sobol [default: sobol]
+        --filter <NAME>         Reconstruction filter, box, tent, gaussian, mitchell or lanczos [default: box]
+        --filter-radius <PIXELS>
+                                Radius of the filter [default: 0.5 for box, 1 for tent, 1.5 for gaussian, 2 for mitchell and lanczos]
+        --integrator <NAME>     What each sample computes, path for the path tracer, or normals, albedo, depth, ao (ambient occlusion) or material-id for debugging [default: path]
+        --far <DISTANCE>        Distance shown as white by the depth integrator [default: twice the distance to the point looked at]
+        --ao-radius <DISTANCE>  Distance within which objects occlude with the ao integrator [default: unlimited]
+        --scene <NAME|FILE>     Built-in scene (marble, spheres, random or cornell) or scene file [default: $SCENE or marble]
+    -o, --output <PATH>         Where to save the image [default: image.png]
+        --format <FORMAT>       png, jpeg, bmp, tiff, pnm or ico, or exr, hdr or pfm for linear radiance [default: guessed from the output]
+        --exposure <STOPS>      Exposure of the displayed image, each stop doubling the brightness [default: 0]
+        --tonemap <NAME>        clamp, reinhard, extended-reinhard, aces or hable [default: clamp]
+        --white <LUMINANCE>     Luminance becoming white with extended-reinhard [default: 4]
+        --dither                Dither the displayed image to hide the banding
+        --exr-float             Write 32-bit floats instead of halves in EXR files
+        --exr-variance          Add the variance of each pixel as a layer of EXR files
+        --no-bvh                Test every object for each ray instead of building a BVH
+        --headless              Render without opening a window, print the progress and save the image
+        --help                  Print this message";
+
+/// Everything that can be tuned when launching the raytracer.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub width: usize,
+    pub height: usize,
+    pub ray_per_pixel: usize,
+    pub depths: Depths,
+    pub chunk_width: usize,
+    pub chunk_height: usize,
+    pub nb_workers: usize,
+    pub seed: u64,
+    pub generator: Generator,
+    pub sampler: SamplerKind,
+    pub filter: FilterKind,
+    /// `None` means the default radius of the filter
+    pub filter_radius: Option<f32>,
+    pub integrator: IntegratorKind,
+    /// `None` means relative to the camera
+    pub far: Option<f32>,
+    /// `None` means unlimited
+    pub ao_radius: Option<f32>,
+    pub scene: String,
+    pub output: PathBuf,
+    /// `None` means guessed from the extension of `output`
+    pub format: Option<OutputFormat>,
+    /// Only applied to the window and to the 8-bit formats
+    pub tone_map: ToneMap,
+    pub exr_float: bool,
+    pub exr_variance: bool,
+    pub bvh: bool,
+    pub headless: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 800,
+            ray_per_pixel: 100,
+            depths: Depths::default(),
+            chunk_width: 50,
+            chunk_height: 50,
+            nb_workers: 10,
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            generator: Generator::default(),
+            sampler: SamplerKind::default(),
+            filter: FilterKind::default(),
+            filter_radius: None,
+            integrator: IntegratorKind::default(),
+            far: None,
+            ao_radius: None,
+            scene: env::var("SCENE").unwrap_or_else(|_| String::from("marble")),
+            output: PathBuf::from("image.png"),
+            format: None,
+            tone_map: ToneMap::default(),
+            exr_float: false,
+            exr_variance: false,
+            bvh: true,
+            headless: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// `--help` was asked, the caller should print `USAGE`
+    Help,
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue {
+        arg: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Help => write!(f, "{}", USAGE),
+            ConfigError::UnknownArgument(arg) => write!(f, "unknown argument `{}`", arg),
+            ConfigError::MissingValue(arg) => write!(f, "missing value for `{}`", arg),
+            ConfigError::InvalidValue { arg, value } => {
+                write!(f, "invalid value `{}` for `{}`", value, arg)
+            }
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_args(env::args().skip(1))
+    }
+
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Accept both `--width 800` and `--width=800`
+            let (name, inline_value) = match arg.find('=') {
+                Some(index) if arg.starts_with("--") => {
+                    (arg[..index].to_string(), Some(arg[index + 1..].to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ConfigError::MissingValue(name.clone()))
+            };
+
+            match name.as_str() {
+                "-w" | "--width" => config.width = parse_positive(&name, value()?)?,
+                "-h" | "--height" => config.height = parse_positive(&name, value()?)?,
+                "-s" | "--samples" => config.ray_per_pixel = parse_positive(&name, value()?)?,
+                "-d" | "--depth" => config.depths.set_all(parse(&name, value()?)?),
+                "--diffuse-depth" => config.depths.diffuse = parse(&name, value()?)?,
+                "--specular-depth" => config.depths.specular = parse(&name, value()?)?,
+                "--transmission-depth" => config.depths.transmission = parse(&name, value()?)?,
+                "--rr-depth" => config.depths.russian_roulette = parse(&name, value()?)?,
+                "--chunk" => {
+                    let value = value()?;
+                    let (width, height) = match value.find('x') {
+                        Some(index) => (&value[..index], &value[index + 1..]),
+                        None => (value.as_str(), value.as_str()),
+                    };
+                    config.chunk_width = parse_positive(&name, width.to_string())?;
+                    config.chunk_height = parse_positive(&name, height.to_string())?;
+                }
+                "-j" | "--workers" => config.nb_workers = parse_positive(&name, value()?)?,
+                "--seed" => config.seed = parse(&name, value()?)?,
+                "--rng" => config.generator = parse(&name, value()?)?,
+                "--sampler" => config.sampler = parse(&name, value()?)?,
+                "--filter" => config.filter = parse(&name, value()?)?,
+                "--filter-radius" => {
+                    config.filter_radius = Some(parse_positive_float(&name, value()?)?)
+                }
+                "--integrator" => config.integrator = parse(&name, value()?)?,
+                "--far" => config.far = Some(parse_positive_float(&name, value()?)?),
+                "--ao-radius" => config.ao_radius = Some(parse_positive_float(&name, value()?)?),
+                "--scene" => config.scene = value()?,
+                "-o" | "--output" => config.output = PathBuf::from(value()?),
+                "--format" => {
+                    let value = value()?;
+                    config.format = Some(OutputFormat::from_name(&value).ok_or(
+                        ConfigError::InvalidValue {
+                            arg: name.clone(),
+                            value,
+                        },
+                    )?);
+                }
+                "--exposure" => config.tone_map.exposure = parse(&name, value()?)?,
+                "--tonemap" => config.tone_map.operator = parse(&name, value()?)?,
+                "--white" => config.tone_map.white = parse_positive_float(&name, value()?)?,
+                "--dither" => config.tone_map.dither = true,
+                "--exr-float" => config.exr_float = true,
+                "--exr-variance" => config.exr_variance = true,
+                "--no-bvh" => config.bvh = false,
+                "--headless" => config.headless = true,
+                "--help" => return Err(ConfigError::Help),
+                _ => return Err(ConfigError::UnknownArgument(arg)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    #[inline]
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn filter(&self) -> Filter {
+        Filter::new(self.filter, self.filter_radius)
+    }
+
+    pub fn integrator(&self, camera: &CameraSettings) -> Arc<dyn Integrator> {
+        self.integrator
+            .build(self.depths, self.far, self.ao_radius, camera)
+    }
+
+    pub fn output_format(&self) -> Result<OutputFormat, OutputError> {
+        self.format
+            .or_else(|| OutputFormat::from_path(&self.output))
+            .ok_or_else(|| OutputError::UnknownFormat(self.output.clone()))
+    }
+}
+
+fn parse<T: FromStr>(arg: &str, value: String) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        arg: arg.to_string(),
+        value,
+    })
+}
+
+fn parse_positive(arg: &str, value: String) -> Result<usize, ConfigError> {
+    match parse(arg, value.clone())? {
+        0 => Err(ConfigError::InvalidValue {
+            arg: arg.to_string(),
+            value,
+        }),
+        n => Ok(n),
+    }
+}
+
+fn parse_positive_float(arg: &str, value: String) -> Result<f32, ConfigError> {
+    match parse::<f32>(arg, value.clone())? {
+        x if x > 0.0 && x.is_finite() => Ok(x),
+        _ => Err(ConfigError::InvalidValue {
+            arg: arg.to_string(),
+            value,
+        }),
+    }
+}
@@ -0,0 +1,66 @@
+use ::std::fmt;
+use ::std::path::PathBuf;
+
+use crate::config::ConfigError;
+use crate::output::OutputError;
+use crate::scene_file::SceneError;
+
+/// Everything that can make the raytracer stop before saving the image
+#[derive(Debug)]
+pub enum Error {
+    Config(ConfigError),
+    UnknownScene(String),
+    SceneFile(PathBuf, SceneError),
+    Window(minifb::Error),
+    WindowClosed,
+    /// Number of workers which panicked
+    Worker(usize),
+    Output(OutputError),
+}
+
+impl Error {
+    /// Exit code of the process, bad usage is reported as 2 like most command line tools
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) | Error::UnknownScene(_) | Error::SceneFile(..) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::UnknownScene(scene) => write!(f, "unknown scene `{}`", scene),
+            Error::SceneFile(path, e @ SceneError::Io(_)) => {
+                write!(f, "cannot read {}: {}", path.display(), e)
+            }
+            // Already located in the OBJ file
+            Error::SceneFile(_, SceneError::Obj(e)) => write!(f, "{}", e),
+            Error::SceneFile(path, e) => write!(f, "{}:{}", path.display(), e),
+            Error::Window(e) => write!(f, "cannot open the window: {}", e),
+            Error::WindowClosed => write!(f, "the window was closed before the end of the render"),
+            Error::Worker(count) => write!(f, "{} worker(s) panicked during the render", count),
+            Error::Output(e) => write!(f, "cannot save the image: {}", e),
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<minifb::Error> for Error {
+    fn from(e: minifb::Error) -> Self {
+        Error::Window(e)
+    }
+}
+
+impl From<OutputError> for Error {
+    fn from(e: OutputError) -> Self {
+        Error::Output(e)
+    }
+}
@@ -0,0 +1,227 @@
+use crate::filter::Filter;
+use crate::vec3::Vec3;
+
+/// Radiance accumulated in a pixel, in linear RGB. The sums are kept in `f64`, in which the
+/// product of two `f32` is exact.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pixel {
+    /// Sum of the samples times their weight
+    sum: [f64; 3],
+    weight: f64,
+    /// Sums of the squared weights, of the samples and of the squared samples times the
+    /// squared weights, for the variance
+    squared_weight: f64,
+    squared_weight_sum: [f64; 3],
+    squared_weight_squares: [f64; 3],
+}
+
+impl Pixel {
+    fn add_sample(&mut self, color: Vec3, weight: f32) {
+        let weight = weight as f64;
+        let squared_weight = weight * weight;
+        self.weight += weight;
+        self.squared_weight += squared_weight;
+        for channel in 0..3 {
+            let value = color[channel] as f64;
+            self.sum[channel] += weight * value;
+            self.squared_weight_sum[channel] += squared_weight * value;
+            self.squared_weight_squares[channel] += squared_weight * value * value;
+        }
+    }
+
+    fn add(&mut self, other: &Pixel) {
+        self.weight += other.weight;
+        self.squared_weight += other.squared_weight;
+        for channel in 0..3 {
+            self.sum[channel] += other.sum[channel];
+            self.squared_weight_sum[channel] += other.squared_weight_sum[channel];
+            self.squared_weight_squares[channel] += other.squared_weight_squares[channel];
+        }
+    }
+
+    /// Weighted mean of the samples, black without any
+    pub fn color(&self) -> Vec3 {
+        if self.weight == 0.0 {
+            return Vec3::default();
+        }
+        let mean = |channel: usize| (self.sum[channel] / self.weight) as f32;
+        Vec3::new(mean(0), mean(1), mean(2))
+    }
+
+    /// Variance of the weighted mean of the samples, an estimate of the noise left in the
+    /// pixel. It is corrected with the effective number of samples, which is the number of
+    /// samples when they all have the same weight.
+    pub fn variance(&self) -> Vec3 {
+        let samples = self.weight * self.weight / self.squared_weight;
+        if self.weight == 0.0 || samples.is_nan() || samples <= 1.0 {
+            return Vec3::default();
+        }
+        let variance = |channel: usize| {
+            let mean = self.sum[channel] / self.weight;
+            let deviations = self.squared_weight_squares[channel]
+                - 2.0 * mean * self.squared_weight_sum[channel]
+                + mean * mean * self.squared_weight;
+            // Rounding can make it slightly negative
+            (deviations / (self.squared_weight * (samples - 1.0))).max(0.0) as f32
+        };
+        Vec3::new(variance(0), variance(1), variance(2))
+    }
+}
+
+/// High dynamic range image the samples are added to. Nothing is clamped nor tone mapped
+/// here, the conversion to a displayable image is done afterwards.
+#[derive(Debug, Clone)]
+pub struct Film {
+    /// Position of the first pixel in the whole image, only tiles have one
+    x0: usize,
+    y0: usize,
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::tile(0, 0, width, height)
+    }
+
+    /// Part of an image with its first pixel at `(x0, y0)`, to be added to the whole image
+    /// with `merge`
+    pub fn tile(x0: usize, y0: usize, width: usize, height: usize) -> Self {
+        Self {
+            x0,
+            y0,
+            width,
+            height,
+            pixels: vec![Pixel::default(); width * height],
+        }
+    }
+
+    /// Splat the sample taken at `(x, y)`, in pixels from the top left of the whole image, on
+    /// every pixel of the film within the radius of the filter. A sample lying on the
+    /// border of two pixels belongs to the one after it, as with a plain average.
+    pub fn add_sample(&mut self, x: f32, y: f32, color: Vec3, filter: &Filter) {
+        // Pixels whose center c satisfies x - radius < c <= x + radius
+        let range = |position: f32, first: usize, size: usize| {
+            let start = (position - 0.5 - filter.radius).floor() + 1.0;
+            let end = (position - 0.5 + filter.radius).floor() + 1.0;
+            let clamp = |p: f32| (p.max(first as f32) as usize).min(first + size);
+            clamp(start)..clamp(end)
+        };
+
+        for py in range(y, self.y0, self.height) {
+            for px in range(x, self.x0, self.width) {
+                let weight = filter.evaluate(px as f32 + 0.5 - x, py as f32 + 0.5 - y);
+                if weight != 0.0 {
+                    let index = (px - self.x0) + (py - self.y0) * self.width;
+                    self.pixels[index].add_sample(color, weight);
+                }
+            }
+        }
+    }
+
+    /// Add the pixels of `tile`, which must lie inside this film
+    pub fn merge(&mut self, tile: &Film) {
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let index = (tile.x0 + x - self.x0) + (tile.y0 + y - self.y0) * self.width;
+                self.pixels[index].add(&tile.pixels[x + y * tile.width]);
+            }
+        }
+    }
+
+    /// Mean radiance of each pixel, row by row from the top
+    pub fn colors(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.pixels.iter().map(Pixel::color)
+    }
+
+    /// Variance of each pixel, row by row from the top
+    pub fn variances(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.pixels.iter().map(Pixel::variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::ops::Range;
+
+    use super::*;
+    use crate::filter::FilterKind;
+
+    /// Pixels with samples, in the coordinates of the whole image
+    fn touched(film: &Film) -> Vec<(usize, usize)> {
+        let mut pixels = Vec::new();
+        for y in 0..film.height {
+            for x in 0..film.width {
+                if film.pixels[x + y * film.width].weight != 0.0 {
+                    pixels.push((film.x0 + x, film.y0 + y));
+                }
+            }
+        }
+        pixels
+    }
+
+    fn square(xs: Range<usize>, ys: Range<usize>) -> Vec<(usize, usize)> {
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y))).collect()
+    }
+
+    #[test]
+    fn box_sample_in_one_pixel() {
+        let filter = Filter::new(FilterKind::Box, None);
+        let mut film = Film::new(4, 4);
+        film.add_sample(2.3, 1.7, Vec3::new(1.0, 1.0, 1.0), &filter);
+        assert_eq!(touched(&film), [(2, 1)]);
+
+        // On the border between two pixels, in the one after it
+        let mut film = Film::new(4, 4);
+        film.add_sample(1.0, 3.0, Vec3::new(1.0, 1.0, 1.0), &filter);
+        assert_eq!(touched(&film), [(1, 3)]);
+    }
+
+    #[test]
+    fn wide_filter_range() {
+        // Centers from 3.5 to 6.5 are within 2 of 5
+        let filter = Filter::new(FilterKind::Mitchell, Some(2.0));
+        let mut film = Film::new(10, 10);
+        film.add_sample(5.0, 5.0, Vec3::new(1.0, 1.0, 1.0), &filter);
+        assert_eq!(touched(&film), square(3..7, 3..7));
+
+        // The tent is zero for the centers at exactly the radius
+        let filter = Filter::new(FilterKind::Tent, Some(1.0));
+        let mut film = Film::new(10, 10);
+        film.add_sample(2.25, 2.5, Vec3::new(1.0, 1.0, 1.0), &filter);
+        assert_eq!(touched(&film), square(1..3, 2..3));
+    }
+
+    #[test]
+    fn range_clamped_to_the_film() {
+        let filter = Filter::new(FilterKind::Gaussian, Some(1.5));
+        let mut film = Film::new(4, 4);
+        film.add_sample(0.1, 3.9, Vec3::new(1.0, 1.0, 1.0), &filter);
+        assert_eq!(touched(&film), square(0..2, 2..4));
+
+        let mut tile = Film::tile(2, 2, 3, 3);
+        tile.add_sample(2.1, 2.1, Vec3::new(1.0, 1.0, 1.0), &filter);
+        assert_eq!(touched(&tile), square(2..4, 2..4));
+    }
+
+    #[test]
+    fn merged_tile_same_as_film() {
+        let filter = Filter::new(FilterKind::Lanczos, None);
+        let samples = [(3.2, 4.7), (4.5, 4.5), (5.9, 3.1), (3.0, 6.0)];
+        let color = Vec3::new(0.25, 0.5, 2.0);
+
+        let mut film = Film::new(10, 10);
+        let mut tile = Film::tile(1, 1, 8, 8);
+        for &(x, y) in samples.iter() {
+            film.add_sample(x, y, color, &filter);
+            tile.add_sample(x, y, color, &filter);
+        }
+        let mut merged = Film::new(10, 10);
+        merged.merge(&tile);
+
+        for (a, b) in film.colors().zip(merged.colors()) {
+            assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+        }
+    }
+}
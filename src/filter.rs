@@ -0,0 +1,187 @@
+use ::std::f32::consts::PI;
+use ::std::str::FromStr;
+
+/// Reconstruction filters, the weight of a sample in a pixel depending on its distance to the
+/// center of the pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterKind {
+    /// Every sample inside the pixel counts the same, which is the plain average
+    #[default]
+    Box,
+    Tent,
+    /// Truncated at the radius, three standard deviations away
+    Gaussian,
+    /// Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    /// Sinc windowed by a wider sinc, with as many lobes as the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    /// Radius used unless another one is given, in pixels
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 2.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "box" => Ok(FilterKind::Box),
+            "tent" | "triangle" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" | "mitchell-netravali" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// Samples further than this from the center of a pixel along x or y do not count in it
+    pub radius: f32,
+    /// Inverse of the integral of the filter along one axis
+    normalization: f32,
+}
+
+impl Filter {
+    /// Steps of the numerical integration giving the normalization
+    const STEPS: usize = 1024;
+
+    pub fn new(kind: FilterKind, radius: Option<f32>) -> Self {
+        let mut filter = Self {
+            kind,
+            radius: radius.unwrap_or_else(|| kind.default_radius()),
+            normalization: 1.0,
+        };
+        // Midpoint rule, most filters having no closed form integral
+        let step = 2.0 * filter.radius / Self::STEPS as f32;
+        let integral: f32 = (0..Self::STEPS)
+            .map(|i| filter.evaluate_1d(-filter.radius + (i as f32 + 0.5) * step) * step)
+            .sum();
+        filter.normalization = integral.recip();
+        filter
+    }
+
+    /// Weight of a sample at `(dx, dy)` from the center of a pixel, which can be negative.
+    /// The filter integrates to 1 over the plane.
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy) * (self.normalization * self.normalization)
+    }
+
+    /// All the filters are separable
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        let radius = self.radius;
+        if x > radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / radius,
+            FilterKind::Gaussian => {
+                let gaussian = |x: f32| {
+                    let sigma = radius / 3.0;
+                    (-x * x / (2.0 * sigma * sigma)).exp()
+                };
+                // Shifted so that it goes to zero at the radius
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            FilterKind::Mitchell => {
+                const B: f32 = 1.0 / 3.0;
+                const C: f32 = 1.0 / 3.0;
+                // The polynomials are defined on [0, 2]
+                let t = 2.0 * x / radius;
+                let value = if t < 1.0 {
+                    (12.0 - 9.0 * B - 6.0 * C) * t * t * t
+                        + (-18.0 + 12.0 * B + 6.0 * C) * t * t
+                        + (6.0 - 2.0 * B)
+                } else {
+                    (-B - 6.0 * C) * t * t * t
+                        + (6.0 * B + 30.0 * C) * t * t
+                        + (-12.0 * B - 48.0 * C) * t
+                        + (8.0 * B + 24.0 * C)
+                };
+                value / 6.0
+            }
+            FilterKind::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+/// Normalized sinc, `sin(πx) / πx`
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    #[test]
+    fn normalized() {
+        for &kind in KINDS.iter() {
+            for &radius in [None, Some(0.8), Some(2.5)].iter() {
+                let filter = Filter::new(kind, radius);
+                // Midpoint rule on a grid finer than the one of the normalization
+                let steps = 300;
+                let step = 2.0 * filter.radius / steps as f32;
+                let position = |i: usize| -filter.radius + (i as f32 + 0.5) * step;
+                let mut integral = 0.0;
+                for j in 0..steps {
+                    for i in 0..steps {
+                        integral += filter.evaluate(position(i), position(j)) * step * step;
+                    }
+                }
+                assert!(
+                    (integral - 1.0).abs() < 1e-3,
+                    "{:?} of radius {}: {}",
+                    kind,
+                    filter.radius,
+                    integral
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_at_the_radius() {
+        for &kind in KINDS.iter() {
+            let filter = Filter::new(kind, Some(1.7));
+            let r = filter.radius;
+            // Nothing beyond the radius
+            for &(dx, dy) in [(r * 1.01, 0.0), (0.0, -r * 1.01), (-2.0 * r, 2.0 * r)].iter() {
+                assert_eq!(filter.evaluate(dx, dy), 0.0, "{:?}", kind);
+            }
+            // Only the box is not continuous there
+            if kind != FilterKind::Box {
+                for &(dx, dy) in [(r, 0.0), (-r, 0.0), (0.3, r), (0.0, -r)].iter() {
+                    assert!(filter.evaluate(dx, dy).abs() < 1e-6, "{:?}", kind);
+                }
+            }
+        }
+    }
+}
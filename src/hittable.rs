@@ -2,13 +2,106 @@ use ::std::fmt::Debug;
 use ::std::sync::Arc;
 
 use crate::aabb::AABB;
+use crate::background::Background;
+use crate::bvh::BVH;
+use crate::light::{Incident, Light};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub trait Hittable: Send + Sync + Debug {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
+
+    /// Whether it gives off light, in which case the scene samples it when it supports
+    /// `sample_point`
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Random point of the surface at `time` as seen from `origin`, `None` if it cannot be
+    /// sampled
+    fn sample_point(&self, _origin: &Vec3, _time: f32, _sampler: &mut dyn Sampler) -> Option<Vec3> {
+        None
+    }
+
+    /// Density of `sample_point` giving a point in `direction` from `origin` at `time`, with
+    /// respect to the solid angle
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3, _time: f32) -> f32 {
+        0.0
+    }
+}
+
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+
+    fn sample_point(&self, origin: &Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        (**self).sample_point(origin, time, sampler)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        (**self).pdf_value(origin, direction, time)
+    }
+}
+
+/// Density with respect to the solid angle of a point picked uniformly on a surface of area
+/// `area`, seen in `direction` at the distance `t` along it where the normal is `normal`
+pub fn area_to_solid_angle_pdf(direction: &Vec3, t: f32, normal: &Vec3, area: f32) -> f32 {
+    let distance_squared = t * t * direction.squared_length();
+    let cosine = (direction.dot(normal) / direction.length()).abs();
+    if cosine > 0.0 {
+        distance_squared / (cosine * area)
+    } else {
+        0.0
+    }
+}
+
+/// Makes a hittable face the other way
+#[derive(Debug)]
+pub struct FlipNormals {
+    hittable: Arc<dyn Hittable>,
+}
+
+impl FlipNormals {
+    pub fn new(hittable: Arc<dyn Hittable>) -> Self {
+        Self { hittable }
+    }
+}
+
+impl Hittable for FlipNormals {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hittable.hit(ray, t_min, t_max).map(|rec| HitRecord {
+            normal: -rec.normal,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1)
+    }
+
+    fn is_light(&self) -> bool {
+        self.hittable.is_light()
+    }
+
+    fn sample_point(&self, origin: &Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        self.hittable.sample_point(origin, time, sampler)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        self.hittable.pdf_value(origin, direction, time)
+    }
 }
 
 #[derive(Debug)]
@@ -16,23 +109,155 @@ pub struct HitRecord {
     pub t: f32,
     pub p: Vec3,
     pub normal: Vec3,
+    /// Texture coordinates
+    pub u: f32,
+    pub v: f32,
     pub material: Arc<dyn Material>,
 }
 
+/// What `Scene::sample_light` picked
+#[derive(Debug)]
+pub enum LightSample {
+    /// Point on one of the hittables giving off light, whose density is `Scene::light_pdf`
+    Surface(Vec3),
+    /// Light from one of the lights without a surface, already divided by the chance of
+    /// picking it
+    Source(Incident),
+}
+
 #[derive(Default, Debug)]
 pub struct Scene {
     pub hittables: Vec<Arc<dyn Hittable>>,
+    /// The hittables giving off light, sampled at each diffuse bounce
+    pub lights: Vec<Arc<dyn Hittable>>,
+    /// Point, spot and directional lights, which are sampled along with `lights` but can
+    /// never be hit
+    pub delta_lights: Vec<Arc<dyn Light>>,
+    pub background: Background,
+    /// Hierarchy over the bounded hittables, built by `build_bvh`
+    bvh: Option<BVH<Arc<dyn Hittable>>>,
+    /// Hittables without bounding box, always tested when there is a BVH
+    unbounded: Vec<Arc<dyn Hittable>>,
 }
 
 impl Scene {
     pub fn new(hittables: Vec<Arc<dyn Hittable>>) -> Self {
-        Self { hittables }
+        let lights = hittables
+            .iter()
+            .filter(|hittable| hittable.is_light())
+            .cloned()
+            .collect();
+        Self {
+            hittables,
+            lights,
+            delta_lights: Vec::new(),
+            background: Background::default(),
+            bvh: None,
+            unbounded: Vec::new(),
+        }
+    }
+
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn with_lights(mut self, lights: Vec<Arc<dyn Light>>) -> Self {
+        self.delta_lights = lights;
+        self
+    }
+
+    /// Whether anything can be found by sampling the lights
+    pub fn has_lights(&self) -> bool {
+        self.light_count() > 0
+    }
+
+    fn light_count(&self) -> usize {
+        self.lights.len() + self.delta_lights.len()
+    }
+
+    /// Speed up `hit` for rays whose time is in `[initial_time, final_time]`
+    pub fn build_bvh(&mut self, initial_time: f32, final_time: f32) {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = self
+            .hittables
+            .iter()
+            .cloned()
+            .partition(|hittable| hittable.bounding_box(initial_time, final_time).is_some());
+
+        self.bvh = if bounded.is_empty() {
+            None
+        } else {
+            Some(BVH::new(bounded, initial_time, final_time))
+        };
+        self.unbounded = unbounded;
     }
 
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        match &self.bvh {
+            Some(bvh) => {
+                let hit_record = bvh.hit(ray, t_min, t_max);
+                let closest_so_far = hit_record.as_ref().map_or(t_max, |rec| rec.t);
+                Self::hit_list(&self.unbounded, ray, t_min, closest_so_far).or(hit_record)
+            }
+            None => self.hit_brute_force(ray, t_min, t_max),
+        }
+    }
+
+    /// Light seen from `origin` at `time` on one of the lights picked uniformly, both with and
+    /// without a surface, `None` without lights or if the light cannot be sampled
+    pub fn sample_light(
+        &self,
+        origin: &Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<LightSample> {
+        let count = self.light_count();
+        if count == 0 {
+            return None;
+        }
+        let index = ((sampler.get_1d() * count as f32) as usize).min(count - 1);
+        if index < self.lights.len() {
+            self.lights[index]
+                .sample_point(origin, time, sampler)
+                .map(LightSample::Surface)
+        } else {
+            let light = &self.delta_lights[index - self.lights.len()];
+            let mut incident = light.sample(origin, sampler)?;
+            incident.light *= count as f32;
+            Some(LightSample::Source(incident))
+        }
+    }
+
+    /// Density of `sample_light` giving a point in `direction` from `origin` at `time`, with
+    /// respect to the solid angle. The directions where it is zero are never picked by
+    /// sampling the lights.
+    pub fn light_pdf(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        // The lights without a surface only lower the chance of picking the others
+        let sum: f32 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.light_count() as f32
+    }
+
+    /// Test every hittable, whether or not the BVH is built
+    pub fn hit_brute_force(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        Self::hit_list(&self.hittables, ray, t_min, t_max)
+    }
+
+    fn hit_list(
+        hittables: &[Arc<dyn Hittable>],
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<HitRecord> {
         let mut hit_record = None;
         let mut closest_so_far = t_max;
-        for hittable in self.hittables.iter() {
+        for hittable in hittables.iter() {
             if let Some(rec) = hittable.hit(ray, t_min, closest_so_far) {
                 hit_record = Some(rec);
                 closest_so_far = hit_record.as_ref().map(|rec| rec.t).unwrap();
@@ -41,25 +266,89 @@ impl Scene {
         hit_record
     }
 
+    #[allow(dead_code)]
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         if self.hittables.is_empty() {
             return None;
         }
 
-        let mut aabb = if let Some(aabb) = self.hittables[0].bounding_box(t0, t1) {
-            aabb
-        } else {
-            return None;
-        };
+        let mut aabb = self.hittables[0].bounding_box(t0, t1)?;
 
-        for i in 1..self.hittables.len() {
-            if let Some(temp_box) = self.hittables[i].bounding_box(t0, t1) {
-                aabb = AABB::surrounding_box(aabb, temp_box);
-            } else {
-                return None;
-            }
+        for hittable in self.hittables.iter().skip(1) {
+            let temp_box = hittable.bounding_box(t0, t1)?;
+            aabb = AABB::surrounding_box(aabb, temp_box);
         }
 
         Some(aabb)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::mesh::TriangleMesh;
+    use crate::moving_sphere::MovingSphere;
+    use crate::random::Generator;
+    use crate::rect::Box;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn emitters_are_lights() {
+        let white = Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)));
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(white.clone()));
+        let diffuse: Arc<dyn Material> = Arc::new(Lambertian::new(white));
+
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let indices = vec![[0, 1, 2], [0, 2, 3]];
+        let mesh = |material: &Arc<dyn Material>| {
+            TriangleMesh::new(
+                positions.clone(),
+                Vec::new(),
+                Vec::new(),
+                indices.clone(),
+                material.clone(),
+            )
+        };
+        let cube = |material: &Arc<dyn Material>| {
+            Box::new(Vec3::default(), Vec3::new(1.0, 2.0, 3.0), material.clone())
+        };
+        let moving = |material: &Arc<dyn Material>| {
+            let (c0, c1) = (Vec3::default(), Vec3::new(0.0, 1.0, 0.0));
+            MovingSphere::new(c0, c1, 0.0, 1.0, 0.5, material.clone())
+        };
+
+        let mut hittables: Vec<Arc<dyn Hittable>> = Vec::new();
+        for material in [&light, &diffuse].iter() {
+            hittables.push(Arc::new(mesh(material)));
+            hittables.push(Arc::new(cube(material)));
+            hittables.push(Arc::new(moving(material)));
+            hittables.push(Arc::new(Sphere::new(
+                Vec3::default(),
+                1.0,
+                (*material).clone(),
+            )));
+        }
+        let mut scene = Scene::new(hittables);
+        scene.build_bvh(0.0, 1.0);
+
+        assert!(scene.has_lights());
+        assert_eq!(scene.lights.len(), 4);
+
+        let origin = Vec3::new(5.0, 5.0, 5.0);
+        let mut sampler = SamplerKind::Independent.build(1, 1, Generator::Pcg32);
+        for (index, light) in scene.lights.iter().enumerate() {
+            sampler.start_sample(0, index as u64);
+            // A point of the surface, in a direction where the density is not zero
+            let point = light.sample_point(&origin, 0.5, &mut *sampler).unwrap();
+            assert!(light.pdf_value(&origin, &(point - origin), 0.5) > 0.0);
+        }
+    }
+}
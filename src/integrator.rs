@@ -0,0 +1,346 @@
+//! What is computed for each camera ray: the light it brings back with the path tracer, or
+//! one property of what it hits with the debug integrators.
+
+use ::std::f32::consts::PI;
+use ::std::fmt::Debug;
+use ::std::str::FromStr;
+use ::std::sync::Arc;
+
+use crate::camera::CameraSettings;
+use crate::hittable::{HitRecord, LightSample, Scene};
+use crate::material::ScatterKind;
+use crate::random;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+pub trait Integrator: Send + Sync + Debug {
+    /// Value of a sample taken along the camera ray `ray`
+    fn radiance(&self, ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+/// The integrators which can be chosen for a render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegratorKind {
+    #[default]
+    Path,
+    Normals,
+    Albedo,
+    Depth,
+    AmbientOcclusion,
+    MaterialId,
+}
+
+impl IntegratorKind {
+    /// `far` and `ao_radius` only matter to the debug integrators using them, the first one
+    /// defaulting to twice the distance from the camera to the point it looks at
+    pub fn build(
+        self,
+        depths: Depths,
+        far: Option<f32>,
+        ao_radius: Option<f32>,
+        camera: &CameraSettings,
+    ) -> Arc<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Arc::new(PathTracer { depths }),
+            IntegratorKind::Normals => Arc::new(Normals),
+            IntegratorKind::Albedo => Arc::new(Albedo),
+            IntegratorKind::Depth => {
+                let forward = camera.look_at - camera.look_from;
+                Arc::new(Depth {
+                    origin: camera.look_from,
+                    forward: forward.unit_vector(),
+                    far: far.unwrap_or(2.0 * forward.length()),
+                })
+            }
+            IntegratorKind::AmbientOcclusion => Arc::new(AmbientOcclusion {
+                radius: ao_radius.unwrap_or(f32::MAX),
+            }),
+            IntegratorKind::MaterialId => Arc::new(MaterialId),
+        }
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "path" => Ok(IntegratorKind::Path),
+            "normals" | "normal" => Ok(IntegratorKind::Normals),
+            "albedo" => Ok(IntegratorKind::Albedo),
+            "depth" => Ok(IntegratorKind::Depth),
+            "ao" | "ambient-occlusion" => Ok(IntegratorKind::AmbientOcclusion),
+            "material-id" | "material" => Ok(IntegratorKind::MaterialId),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Probability of a path surviving the Russian roulette never goes above this, so that
+/// paths going around between perfect mirrors still end
+const MAX_SURVIVAL: f32 = 0.95;
+
+/// Number of bounces allowed in a path, for each kind of bounce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Depths {
+    pub diffuse: usize,
+    pub specular: usize,
+    pub transmission: usize,
+    /// Bounces after which the paths are randomly ended depending on how much light they
+    /// can still carry, those which go on being brightened to compensate
+    pub russian_roulette: usize,
+}
+
+impl Default for Depths {
+    fn default() -> Self {
+        Self {
+            diffuse: 32,
+            specular: 128,
+            transmission: 128,
+            russian_roulette: 4,
+        }
+    }
+}
+
+impl Depths {
+    /// The same limit for every kind of bounce
+    pub fn set_all(&mut self, depth: usize) {
+        self.diffuse = depth;
+        self.specular = depth;
+        self.transmission = depth;
+    }
+
+    fn limit(&self, kind: ScatterKind) -> usize {
+        match kind {
+            ScatterKind::Diffuse => self.diffuse,
+            ScatterKind::Specular => self.specular,
+            ScatterKind::Transmission => self.transmission,
+        }
+    }
+}
+
+/// Follow paths of bounces from the camera, adding the light found at each of them
+#[derive(Debug)]
+pub struct PathTracer {
+    pub depths: Depths,
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3 {
+        let depths = &self.depths;
+        let mut radiance = Vec3::default();
+        // What the light found at the current vertex is multiplied by
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut ray = ray;
+        // Density of the direction of `ray` when it was scattered by a material for which the
+        // lights were also sampled, the light it finds being then weighted against that strategy
+        let mut pdf = None;
+        // Bounces made so far, of each kind
+        let mut bounces = [0; 3];
+
+        loop {
+            ray = ray.with_sample(sampler.get_1d());
+            let rec = match scene.hit(&ray, 0.001, f32::MAX) {
+                Some(rec) => rec,
+                None => {
+                    radiance += throughput * scene.background.color(&ray);
+                    break;
+                }
+            };
+
+            let mut emitted = rec.material.emitted(rec.u, rec.v, &rec.p);
+            if let Some(pdf) = pdf {
+                if rec.material.is_emissive() {
+                    let light_pdf = scene.light_pdf(&ray.origin(), &ray.direction(), ray.time());
+                    emitted *= power_heuristic(pdf, light_pdf);
+                }
+            }
+            radiance += throughput * emitted;
+
+            // A bounce beyond the limit of its kind ends the path, and the light sampled here
+            // which goes along with it is left out too
+            let scatter = rec.material.scatter(&ray, &rec, sampler);
+            if let Some(scatter) = &scatter {
+                if bounces[scatter.kind as usize] >= depths.limit(scatter.kind) {
+                    break;
+                }
+            }
+
+            let sample_lights = !rec.material.is_specular() && scene.has_lights();
+            if sample_lights {
+                radiance += throughput * direct_light(&ray, &rec, scene, sampler);
+            }
+
+            let scatter = match scatter {
+                Some(scatter) => scatter,
+                None => break,
+            };
+            bounces[scatter.kind as usize] += 1;
+            throughput *= scatter.attenuation;
+            pdf = if sample_lights { scatter.pdf } else { None };
+            ray = scatter.ray;
+
+            if bounces.iter().sum::<usize>() > depths.russian_roulette {
+                let survival = throughput.max_component().min(MAX_SURVIVAL);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        radiance
+    }
+}
+
+/// Light arriving directly from one of the lights and scattered along `ray`. The light
+/// from a surface is weighted against the chance of the material scattering towards it,
+/// the other lights cannot be found that way.
+fn direct_light(ray: &Ray, rec: &HitRecord, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3 {
+    let black = Vec3::default();
+    let point = match scene.sample_light(&rec.p, ray.time(), sampler) {
+        Some(LightSample::Surface(point)) => point,
+        Some(LightSample::Source(incident)) => {
+            let scattering = rec.material.eval(ray, rec, &incident.direction);
+            if scattering.squared_length() == 0.0 {
+                return black;
+            }
+            let shadow =
+                Ray::new(rec.p, incident.direction, ray.time()).with_sample(sampler.get_1d());
+            return match scene.hit(&shadow, 0.001, incident.distance) {
+                Some(_) => black,
+                None => scattering * incident.light,
+            };
+        }
+        None => return black,
+    };
+    let direction = point - rec.p;
+    let light_pdf = scene.light_pdf(&rec.p, &direction, ray.time());
+    let scattering = rec.material.eval(ray, rec, &direction);
+    if light_pdf <= 0.0 || scattering.squared_length() == 0.0 {
+        return black;
+    }
+
+    // The shadow ray finds either the light or what is in the way. Its direction is a unit
+    // vector, so that `t_min` does not skip more of what is in the way for far lights.
+    let shadow = Ray::new(rec.p, direction.unit_vector(), ray.time()).with_sample(sampler.get_1d());
+    match scene.hit(&shadow, 0.001, f32::MAX) {
+        Some(light) => {
+            let emitted = light.material.emitted(light.u, light.v, &light.p);
+            let weight = power_heuristic(light_pdf, rec.material.pdf(ray, rec, &direction));
+            scattering * emitted * (weight / light_pdf)
+        }
+        None => black,
+    }
+}
+
+/// Weight of a sample picked with the density `pdf` when another strategy could have picked
+/// it with the density `other`, from Veach's power heuristic with an exponent of 2
+fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// The normals, mapped from `[-1, 1]` to `[0, 1]`, so that a surface facing the x axis is
+/// red, the y axis green and the z axis blue. Nothing is black.
+#[derive(Debug)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(&ray, 0.001, f32::MAX) {
+            Some(rec) => (rec.normal.unit_vector() + Vec3::new(1.0, 1.0, 1.0)) * 0.5,
+            None => Vec3::default(),
+        }
+    }
+}
+
+/// Base color of the material hit, without any lighting
+#[derive(Debug)]
+pub struct Albedo;
+
+impl Integrator for Albedo {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(&ray, 0.001, f32::MAX) {
+            Some(rec) => rec.material.albedo(&rec),
+            None => Vec3::default(),
+        }
+    }
+}
+
+/// Distance along the view direction, linear from black at the camera to white at `far` and
+/// beyond
+#[derive(Debug)]
+pub struct Depth {
+    origin: Vec3,
+    /// Unit vector
+    forward: Vec3,
+    far: f32,
+}
+
+impl Integrator for Depth {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vec3 {
+        let depth = match scene.hit(&ray, 0.001, f32::MAX) {
+            Some(rec) => ((rec.p - self.origin).dot(&self.forward) / self.far).min(1.0),
+            None => 1.0,
+        };
+        Vec3::new(depth, depth, depth)
+    }
+}
+
+/// Fraction of the hemisphere around the normal, weighted by the cosine, from which nothing
+/// closer than `radius` hides the point. Nothing is white.
+#[derive(Debug)]
+pub struct AmbientOcclusion {
+    radius: f32,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3 {
+        let rec = match scene.hit(&ray, 0.001, f32::MAX) {
+            Some(rec) => rec,
+            None => return Vec3::new(1.0, 1.0, 1.0),
+        };
+        // On the side the ray comes from
+        let mut normal = rec.normal.unit_vector();
+        if normal.dot(&ray.direction()) > 0.0 {
+            normal = -normal;
+        }
+        // Cosine distributed, so each unoccluded direction counts the same
+        let (u, v) = sampler.get_2d();
+        let (sin, cos) = (2.0 * PI * v).sin_cos();
+        let (r, z) = (u.sqrt(), (1.0 - u).max(0.0).sqrt());
+        let (tangent, bitangent) = normal.basis();
+        let direction = tangent * (r * cos) + bitangent * (r * sin) + normal * z;
+
+        let occlusion = Ray::new(rec.p, direction, ray.time()).with_sample(sampler.get_1d());
+        match scene.hit(&occlusion, 0.001, self.radius) {
+            Some(_) => Vec3::default(),
+            None => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// A false color for each material, to check which one every object is made of. The colors
+/// come from the identifiers of the materials, so they are the same from one run to the
+/// other. Nothing is black.
+#[derive(Debug)]
+pub struct MaterialId;
+
+impl Integrator for MaterialId {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vec3 {
+        let rec = match scene.hit(&ray, 0.001, f32::MAX) {
+            Some(rec) => rec,
+            None => return Vec3::default(),
+        };
+        let hash = random::hash(&[rec.material.id() as u64]);
+        // Kept away from black
+        let channel = |shift: u32| 0.2 + 0.8 * random::linear_from_bits(hash << shift);
+        Vec3::new(channel(0), channel(21), channel(42))
+    }
+}
@@ -0,0 +1,162 @@
+//! Lights without any surface, which no ray can hit: they only light the scene through the
+//! shadow rays sent towards them.
+
+use ::std::f32::consts::PI;
+use ::std::fmt::Debug;
+
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+/// Light arriving at a point from one of the lights
+#[derive(Debug, Clone, Copy)]
+pub struct Incident {
+    /// Unit vector towards the light
+    pub direction: Vec3,
+    /// Distance to the light, which is what a shadow ray must not hit anything before
+    pub distance: f32,
+    /// Light arriving along `direction`, already divided by the chance of picking it
+    pub light: Vec3,
+}
+
+pub trait Light: Send + Sync + Debug {
+    /// Light arriving at `origin` from a direction picked on the light, `None` if it does
+    /// not reach it
+    fn sample(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<Incident>;
+}
+
+/// Shining equally in every direction from a point, fading with the squared distance
+#[derive(Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// Light given off in each direction, per unit of solid angle
+    pub intensity: Vec3,
+}
+
+impl PointLight {
+    pub const fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, origin: &Vec3, _sampler: &mut dyn Sampler) -> Option<Incident> {
+        let (direction, distance, fading) = towards(origin, &self.position)?;
+        Some(Incident {
+            direction,
+            distance,
+            light: self.intensity * fading,
+        })
+    }
+}
+
+/// Point light only shining in a cone, smoothly fading out over the edge of the cone
+#[derive(Debug)]
+pub struct SpotLight {
+    pub position: Vec3,
+    /// Unit vector along the axis of the cone, away from the light
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    /// Cosines of the angles from the axis where the fading starts and where it ends
+    cos_falloff_start: f32,
+    cos_total: f32,
+}
+
+impl SpotLight {
+    /// Nothing is lit beyond `angle` from the axis, and the light starts fading `falloff`
+    /// before, both in degrees
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, angle: f32, falloff: f32) -> Self {
+        let start = (angle - falloff).max(0.0);
+        Self {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_falloff_start: start.to_radians().cos(),
+            cos_total: angle.to_radians().cos(),
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, origin: &Vec3, _sampler: &mut dyn Sampler) -> Option<Incident> {
+        let (direction, distance, fading) = towards(origin, &self.position)?;
+        let cosine = -direction.dot(&self.direction);
+        let edge = smoothstep(self.cos_total, self.cos_falloff_start, cosine);
+        if edge <= 0.0 {
+            return None;
+        }
+        Some(Incident {
+            direction,
+            distance,
+            light: self.intensity * (fading * edge),
+        })
+    }
+}
+
+/// Light coming from infinitely far away in about one direction, like the sun. With an
+/// angular diameter, it comes from a disk of the sky and casts soft shadows.
+#[derive(Debug)]
+pub struct DirectionalLight {
+    /// Unit vector towards the light
+    towards: Vec3,
+    /// Light received by a surface facing it
+    pub irradiance: Vec3,
+    /// Cosine of the angular radius
+    cos_radius: f32,
+}
+
+impl DirectionalLight {
+    /// `direction` is the one the light goes in, and `angular_diameter` is in degrees
+    pub fn new(direction: Vec3, irradiance: Vec3, angular_diameter: f32) -> Self {
+        Self {
+            towards: -direction.unit_vector(),
+            irradiance,
+            cos_radius: (angular_diameter / 2.0).to_radians().cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    /// Uniform in the cone of the disk. Its radiance is the irradiance over `π sin²(radius)`,
+    /// and the density is one over the solid angle `2π (1 - cos(radius))`.
+    fn sample(&self, _origin: &Vec3, sampler: &mut dyn Sampler) -> Option<Incident> {
+        let direction = if self.cos_radius < 1.0 {
+            let (u, v) = sampler.get_2d();
+            let z = 1.0 - u * (1.0 - self.cos_radius);
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let (sin, cos) = (2.0 * PI * v).sin_cos();
+            let (tangent, bitangent) = self.towards.basis();
+            tangent * (r * cos) + bitangent * (r * sin) + self.towards * z
+        } else {
+            self.towards
+        };
+        Some(Incident {
+            direction,
+            distance: f32::MAX,
+            light: self.irradiance * (2.0 / (1.0 + self.cos_radius)),
+        })
+    }
+}
+
+/// Unit vector from `origin` to `position`, the distance between them and the inverse of
+/// its square, `None` if they are at the same place
+fn towards(origin: &Vec3, position: &Vec3) -> Option<(Vec3, f32, f32)> {
+    let offset = *position - *origin;
+    let squared_distance = offset.squared_length();
+    if squared_distance <= 0.0 {
+        return None;
+    }
+    let distance = squared_distance.sqrt();
+    Some((offset / distance, distance, 1.0 / squared_distance))
+}
+
+/// 0 below `edge0`, 1 above `edge1`, and a smooth cubic in between
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge1 <= edge0 {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
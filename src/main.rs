@@ -1,146 +1,157 @@
 #![deny(missing_debug_implementations)]
 
-mod camera;
-mod hittable;
-mod material;
-mod ray;
-mod sphere;
-mod vec3;
-#[macro_use]
-mod random;
 mod aabb;
+mod background;
 mod bvh;
+mod camera;
 mod chunk;
+mod config;
+mod error;
+mod film;
+mod filter;
+mod hittable;
+mod integrator;
+mod light;
+mod material;
+mod matrix;
+mod medium;
+mod mesh;
 mod moving_sphere;
+mod obj;
+mod output;
 mod perlin;
+mod random;
+mod ray;
+mod rect;
+mod sampler;
+mod scene_file;
+mod sphere;
 mod texture;
+mod tonemap;
+mod transform;
+mod triangle;
+mod vec3;
 
+use ::std::collections::BTreeMap;
+use ::std::io::{stderr, Write};
 use ::std::path::Path;
+use ::std::process::exit;
 use ::std::sync::{
     mpsc::{channel, TryRecvError},
-    Arc, Mutex,
+    Arc,
 };
 use ::std::thread::sleep;
 use ::std::time::{Duration, Instant};
 
-use image::{ImageBuffer, ImageFormat, Rgb};
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use threadpool::Builder;
 
-use camera::Camera;
+use background::Background;
+use camera::CameraSettings;
 use chunk::Chunk;
-use hittable::{Hittable, Scene};
-use material::{Dielectric, Lambertian, Metal};
+use config::{Config, ConfigError};
+use error::Error;
+use film::Film;
+use hittable::{FlipNormals, Hittable, Scene};
+use material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use matrix::Matrix4;
 use moving_sphere::MovingSphere;
-use random::random;
-use ray::Ray;
+use random::Rng;
+use rect::{XYRect, XZRect, YZRect};
+use scene_file::SceneFile;
 use sphere::Sphere;
 use texture::{CheckerTexture, ConstantTexture, NoiseTexture, Texture};
+use tonemap::ToneMap;
+use transform::Transformed;
 use vec3::Vec3;
 
-const WIDTH: usize = 800;
-const HEIGHT: usize = 800;
-const RAY_PER_PIXEL: usize = 100;
-const MAX_DEPTH: usize = 100;
-const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
-const CHUNK_WIDTH: usize = 50;
-const CHUNK_HEIGHT: usize = 50;
-const NB_WORKERS: usize = 10;
-
-pub fn color(ray: Ray, scene: &Scene, depth: usize) -> Vec3 {
-    if let Some(rec) = scene.hit(&ray, 0.001, std::f32::MAX) {
-        if let Some((attenuation, scattered)) = if depth < MAX_DEPTH {
-            rec.material.scatter(&ray, &rec)
-        } else {
-            None
-        } {
-            attenuation * color(scattered, &scene, depth + 1)
-        } else {
-            Default::default()
+fn main() {
+    match run() {
+        Ok(()) => {}
+        Err(Error::Config(ConfigError::Help)) => println!("{}", config::USAGE),
+        Err(e @ Error::Config(_)) => {
+            eprintln!("error: {}\n\n{}", e, config::USAGE);
+            exit(e.exit_code());
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(e.exit_code());
         }
-    } else {
-        let unit_direction = ray.direction().unit_vector();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
     }
 }
 
-pub fn from_rgb(r: f32, g: f32, b: f32) -> u32 {
-    let (r, g, b) = (r as u32, g as u32, b as u32);
-    (r << 16) | (g << 8) | b
-}
+fn run() -> Result<(), Error> {
+    let config = Config::from_env()?;
+    // Fail before rendering rather than losing the whole render
+    config.output_format()?;
 
-pub fn to_rgb(mut color: u32) -> (u8, u8, u8) {
-    let r = color >> 16;
-    color ^= r << 16;
-    let g = color >> 8;
-    color ^= g << 8;
-    let b = color;
-    (r as u8, g as u8, b as u8)
-}
+    let (width, height) = (config.width, config.height);
+    let mut film = Film::new(width, height);
+    // Can be changed from the window, without rendering again
+    let mut tone_map = config.tone_map;
 
-fn main() {
-    init_rand!();
-    let buffer = Arc::new(Mutex::new(vec![0; WIDTH * HEIGHT]));
-
-    let mut window = Window::new("Raytracer", WIDTH, HEIGHT, WindowOptions::default())
-        .unwrap_or_else(|e| {
-            panic!("{}", e);
-        });
-
-    // Limit to max ~60 fps update rate
-    window.limit_update_rate(Some(Duration::from_micros(16600)));
-
-    let scene = Arc::new(match option_env!("SCENE") {
-        Some("MARBLE") => two_perlin_spheres(),
-        Some("SPHERES") => two_spheres(),
-        Some("RANDOM") => random_scene(),
-        _ => two_perlin_spheres(),
-    });
-    let look_from = Vec3::new(13.0, 2.0, 3.0);
-    let look_at = Vec3::new(0.0, 0.0, 0.0);
-    let camera = Arc::new(Camera::new(
-        look_from,
-        look_at,
-        UP,
-        20.0,
-        WIDTH as f32 / HEIGHT as f32,
-        0.0,
-        10.0,
-        0.0,
-        1.0,
-    ));
+    let mut window = if config.headless {
+        None
+    } else {
+        let mut window = Window::new("Raytracer", width, height, WindowOptions::default())?;
+
+        // Limit to max ~60 fps update rate
+        window.limit_update_rate(Some(Duration::from_micros(16600)));
+        Some(window)
+    };
+
+    let (mut scene, camera) = match config.scene.to_uppercase().as_str() {
+        "MARBLE" => (two_perlin_spheres(), CameraSettings::default()),
+        "SPHERES" => (two_spheres(), CameraSettings::default()),
+        "RANDOM" => (
+            random_scene(&mut *config.generator.seeded(config.seed)),
+            CameraSettings::default(),
+        ),
+        "CORNELL" => cornell_box(),
+        _ if Path::new(&config.scene).is_file() => {
+            let SceneFile { scene, camera } = SceneFile::load(&config.scene)
+                .map_err(|e| Error::SceneFile(config.scene.clone().into(), e))?;
+            (scene, camera)
+        }
+        _ => return Err(Error::UnknownScene(config.scene)),
+    };
+    if config.bvh {
+        scene.build_bvh(camera.initial_time, camera.final_time);
+    }
+    let scene = Arc::new(scene);
+    let integrator = config.integrator(&camera);
+    let camera = Arc::new(camera.build(config.aspect()));
 
     let time = Instant::now();
 
-    let thread_pool = Builder::new().num_threads(NB_WORKERS).build();
+    let nb_chunks_x = (width as f32 / config.chunk_width as f32).ceil() as usize;
+    let nb_chunks_y = (height as f32 / config.chunk_height as f32).ceil() as usize;
+    let nb_chunks = nb_chunks_x * nb_chunks_y;
+    let thread_pool = Builder::new().num_threads(config.nb_workers).build();
+    let config = Arc::new(config);
     let (tx, rx) = channel();
     #[cfg(debug_assertions)]
-    eprintln!(
-        "{} {}",
-        (WIDTH as f32 / CHUNK_WIDTH as f32).ceil() as usize,
-        (HEIGHT as f32 / CHUNK_HEIGHT as f32).ceil() as usize
-    );
-    for j in 0..((HEIGHT as f32 / CHUNK_HEIGHT as f32).ceil() as usize) {
-        for i in 0..((WIDTH as f32 / CHUNK_WIDTH as f32).ceil() as usize) {
+    eprintln!("{} {}", nb_chunks_x, nb_chunks_y);
+    for j in 0..nb_chunks_y {
+        for i in 0..nb_chunks_x {
             let chunk = Chunk::new(
-                CHUNK_WIDTH,
-                CHUNK_HEIGHT,
-                i * CHUNK_WIDTH,
-                j * CHUNK_HEIGHT,
-                Arc::clone(&buffer),
+                i * config.chunk_width,
+                j * config.chunk_height,
                 Arc::clone(&camera),
                 Arc::clone(&scene),
+                Arc::clone(&integrator),
+                Arc::clone(&config),
             );
             let tx = tx.clone();
 
             thread_pool.execute(move || {
                 #[cfg(debug_assertions)]
                 eprintln!("begin {} {}", i, j);
-                chunk.process();
+                let tile = chunk.process();
                 #[cfg(debug_assertions)]
                 eprintln!("end {} {}", i, j);
-                tx.send((i, j)).unwrap();
+                // The receiver is gone only if the render was aborted
+                let _ = tx.send((i, j, tile));
             });
         }
     }
@@ -148,74 +159,129 @@ fn main() {
     drop(tx);
 
     let mut k = 0;
+    // The tiles are merged in the order of the chunks, whichever finishes first, so that the
+    // image does not depend on the number of workers
+    let mut pending = BTreeMap::new();
+    let mut next_chunk = 0;
 
     loop {
-        // i and j are used here in debug only
-        #[allow(unused_variables)]
         match rx.try_recv() {
-            Ok((i, j)) => {
+            Ok((i, j, tile)) => {
                 k += 1;
+                pending.insert(i + j * nb_chunks_x, tile);
+                while let Some(tile) = pending.remove(&next_chunk) {
+                    film.merge(&tile);
+                    next_chunk += 1;
+                }
                 #[cfg(debug_assertions)]
                 eprintln!("pass number {}", k);
                 #[cfg(debug_assertions)]
                 eprintln!("begin render {} {}", i, j);
-                window
-                    .update_with_buffer(&*buffer.lock().unwrap(), WIDTH, HEIGHT)
-                    .unwrap();
+                match window.as_mut() {
+                    Some(window) => {
+                        window.update_with_buffer(&display(&film, &tone_map), width, height)?;
+                        adjust_tone_map(window, &mut tone_map);
+                    }
+                    None => {
+                        eprint!(
+                            "\rrendered {}/{} chunks ({:.0}%) in {:.1?}",
+                            k,
+                            nb_chunks,
+                            100.0 * k as f32 / nb_chunks as f32,
+                            time.elapsed()
+                        );
+                        let _ = stderr().flush();
+                    }
+                }
                 #[cfg(debug_assertions)]
                 eprintln!("end render {} {}", i, j);
                 continue;
             }
             Err(TryRecvError::Disconnected) => break,
             _ => {
-                window
-                    .update_with_buffer(&*buffer.lock().unwrap(), WIDTH, HEIGHT)
-                    .unwrap();
-                sleep(Duration::from_millis(100));
-                if !window.is_open() {
-                    panic!("Window closed!");
+                if let Some(window) = window.as_mut() {
+                    window.update_with_buffer(&display(&film, &tone_map), width, height)?;
+                    adjust_tone_map(window, &mut tone_map);
+                    if !window.is_open() {
+                        return Err(Error::WindowClosed);
+                    }
                 }
+                sleep(Duration::from_millis(100));
                 continue;
             }
         };
     }
 
+    if window.is_none() {
+        eprintln!();
+    }
+
     println!("number of passes: {}", k);
+    // Rendering again with this seed gives the same image
+    println!("seed: {}", config.seed);
 
     thread_pool.join();
 
+    if thread_pool.panic_count() > 0 {
+        return Err(Error::Worker(thread_pool.panic_count()));
+    }
+
     println!("It took {:?}", time.elapsed());
 
+    let mut window = match window {
+        Some(window) => window,
+        None => {
+            output::save(&film, &config, &tone_map)?;
+            eprintln!("Image saved in {}", config.output.display());
+            return Ok(());
+        }
+    };
+
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-        window
-            .update_with_buffer(&*buffer.lock().unwrap(), WIDTH, HEIGHT)
-            .unwrap();
+        window.update_with_buffer(&display(&film, &tone_map), width, height)?;
+        adjust_tone_map(&mut window, &mut tone_map);
         if window.is_key_down(Key::S) {
-            // Save
-            let bytes_buffer = buffer
-                .lock()
-                .unwrap()
-                .iter()
-                .flat_map(|color| {
-                    let (r, g, b) = to_rgb(*color);
-                    vec![r, g, b]
-                })
-                .collect::<Vec<u8>>();
-            let img_buffer =
-                ImageBuffer::<Rgb<u8>, _>::from_vec(WIDTH as u32, HEIGHT as u32, bytes_buffer)
-                    .unwrap();
-            img_buffer
-                .save_with_format(&Path::new("./image.png"), ImageFormat::Png)
-                .unwrap();
+            output::save(&film, &config, &tone_map)?;
 
             #[cfg(debug_assertions)]
             eprintln!("Image saved!")
         }
     }
+
+    Ok(())
 }
 
-fn random_scene() -> Scene {
+/// Pixels of the film as shown in the window, in `0RGB`
+fn display(film: &Film, tone_map: &ToneMap) -> Vec<u32> {
+    tone_map
+        .apply(film)
+        .into_iter()
+        .map(|[r, g, b]| ((r as u32) << 16) | ((g as u32) << 8) | b as u32)
+        .collect()
+}
+
+/// T cycles through the operators, the up and down arrows change the exposure by half a stop
+/// and D toggles the dithering. The title shows the current settings.
+fn adjust_tone_map(window: &mut Window, tone_map: &mut ToneMap) {
+    let mut changed = true;
+    if window.is_key_pressed(Key::T, KeyRepeat::No) {
+        tone_map.operator = tone_map.operator.next();
+    } else if window.is_key_pressed(Key::Up, KeyRepeat::Yes) {
+        tone_map.exposure += 0.5;
+    } else if window.is_key_pressed(Key::Down, KeyRepeat::Yes) {
+        tone_map.exposure -= 0.5;
+    } else if window.is_key_pressed(Key::D, KeyRepeat::No) {
+        tone_map.dither = !tone_map.dither;
+    } else {
+        changed = false;
+    }
+
+    if changed {
+        window.set_title(&format!("Raytracer ({})", tone_map));
+    }
+}
+
+fn random_scene(rng: &mut dyn Rng) -> Scene {
     let n = 500;
     let mut list: Vec<Arc<dyn Hittable>> = Vec::with_capacity(n + 1);
     list.push(Arc::new(Sphere::new(
@@ -229,21 +295,25 @@ fn random_scene() -> Scene {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = random();
-            let center = Vec3::new(a as f32 + 0.9 * random(), 0.2, b as f32 + 0.9 * random());
+            let choose_mat = rng.linear();
+            let center = Vec3::new(
+                a as f32 + 0.9 * rng.linear(),
+                0.2,
+                b as f32 + 0.9 * rng.linear(),
+            );
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     // Diffuse
                     list.push(Arc::new(MovingSphere::new(
                         center,
-                        center + Vec3::new(0.0, 0.5 * random(), 0.0),
+                        center + Vec3::new(0.0, 0.5 * rng.linear(), 0.0),
                         0.0,
                         1.0,
                         0.2,
                         Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(
-                            random() * random(),
-                            random() * random(),
-                            random() * random(),
+                            rng.linear() * rng.linear(),
+                            rng.linear() * rng.linear(),
+                            rng.linear() * rng.linear(),
                         ))))),
                     )));
                 } else if choose_mat < 0.95 {
@@ -253,11 +323,11 @@ fn random_scene() -> Scene {
                         0.2,
                         Arc::new(Metal::new(
                             Vec3::new(
-                                0.5 * (1.0 + random()),
-                                0.5 * (1.0 + random()),
-                                0.5 * (1.0 + random()),
+                                0.5 * (1.0 + rng.linear()),
+                                0.5 * (1.0 + rng.linear()),
+                                0.5 * (1.0 + rng.linear()),
                             ),
-                            0.5 * (1.0 + random()),
+                            0.5 * (1.0 + rng.linear()),
                         )),
                     )));
                 } else {
@@ -318,16 +388,91 @@ fn two_spheres() -> Scene {
 
 fn two_perlin_spheres() -> Scene {
     let pertext: Arc<dyn Texture> = Arc::new(NoiseTexture::new(50.0));
-    let mut vec: Vec<Arc<dyn Hittable>> = Vec::with_capacity(2);
-    vec.push(Arc::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Arc::new(Lambertian::new(Arc::clone(&pertext))),
-    )));
-    vec.push(Arc::new(Sphere::new(
-        Vec3::new(0.0, 2.0, 0.0),
-        2.0,
-        Arc::new(Lambertian::new(Arc::clone(&pertext))),
-    )));
+    let vec: Vec<Arc<dyn Hittable>> = vec![
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Arc::clone(&pertext))),
+        )),
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, 2.0, 0.0),
+            2.0,
+            Arc::new(Lambertian::new(Arc::clone(&pertext))),
+        )),
+    ];
     Scene::new(vec)
 }
+
+/// The usual closed room, lit by a single light in the ceiling
+fn cornell_box() -> (Scene, CameraSettings) {
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(
+        Vec3::new(0.65, 0.05, 0.05),
+    ))));
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(
+        Vec3::new(0.73, 0.73, 0.73),
+    ))));
+    let green: Arc<dyn Material> = Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(
+        Vec3::new(0.12, 0.45, 0.15),
+    ))));
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Arc::new(ConstantTexture::new(
+        Vec3::new(15.0, 15.0, 15.0),
+    ))));
+
+    let vec: Vec<Arc<dyn Hittable>> = vec![
+        Arc::new(FlipNormals::new(Arc::new(YZRect::new(
+            0.0, 555.0, 0.0, 555.0, 555.0, green,
+        )))),
+        Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)),
+        Arc::new(FlipNormals::new(Arc::new(XZRect::new(
+            213.0, 343.0, 227.0, 332.0, 554.0, light,
+        )))),
+        Arc::new(FlipNormals::new(Arc::new(XZRect::new(
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        )))),
+        Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone())),
+        Arc::new(FlipNormals::new(Arc::new(XYRect::new(
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        )))),
+        Arc::new(
+            Transformed::new(
+                Arc::new(rect::Box::new(
+                    Vec3::new(0.0, 0.0, 0.0),
+                    Vec3::new(165.0, 165.0, 165.0),
+                    white.clone(),
+                )),
+                Matrix4::translation(Vec3::new(130.0, 0.0, 65.0)) * Matrix4::rotation_y(-18.0),
+            )
+            .expect("rotation and translation are invertible"),
+        ),
+        Arc::new(
+            Transformed::new(
+                Arc::new(rect::Box::new(
+                    Vec3::new(0.0, 0.0, 0.0),
+                    Vec3::new(165.0, 330.0, 165.0),
+                    white,
+                )),
+                Matrix4::translation(Vec3::new(265.0, 0.0, 295.0)) * Matrix4::rotation_y(15.0),
+            )
+            .expect("rotation and translation are invertible"),
+        ),
+    ];
+
+    let camera = CameraSettings {
+        look_from: Vec3::new(278.0, 278.0, -800.0),
+        look_at: Vec3::new(278.0, 278.0, 0.0),
+        vfov: 40.0,
+        ..CameraSettings::default()
+    };
+
+    (Scene::new(vec).with_background(Background::None), camera)
+}
@@ -1,78 +1,238 @@
+use ::std::f32::consts::PI;
 use ::std::fmt::Debug;
+use ::std::sync::atomic::{AtomicUsize, Ordering};
 use ::std::sync::Arc;
 
 use crate::hittable::HitRecord;
-use crate::random::random;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::texture::Texture;
 use crate::vec3::Vec3;
 
+/// Bounces are limited separately for each kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScatterKind {
+    /// Also the scattering inside a volume
+    Diffuse = 0,
+    /// Reflection, blurred or not
+    Specular = 1,
+    /// Refraction through the surface
+    Transmission = 2,
+}
+
+/// Direction picked by `Material::scatter`
+#[derive(Debug)]
+pub struct Scatter {
+    pub ray: Ray,
+    pub kind: ScatterKind,
+    /// What the light coming along `ray` is multiplied by, which is `eval / pdf` for the
+    /// materials which are not specular
+    pub attenuation: Vec3,
+    /// Density of the direction of `ray` with respect to the solid angle, `None` for a
+    /// specular direction which could not have been picked by sampling the lights
+    pub pdf: Option<f32>,
+}
+
+/// Number of the next material created
+fn next_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 pub trait Material: Send + Sync + Debug {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)>; // Attenuation, scattered
+    /// The materials are numbered in the order they are created, which is the same from one
+    /// run to the other
+    fn id(&self) -> usize;
+
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter>;
+
+    /// Light given off by the material, none by default
+    fn emitted(&self, _u: f32, _v: f32, _p: &Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Base color, without any lighting, for the albedo debug integrator
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Whether `emitted` can be anything but black
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Scattering function times the cosine, the part of the light coming from `direction`
+    /// which leaves along `ray` reversed. Always black for the specular materials.
+    fn eval(&self, _ray: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Density of `scatter` picking `direction`, with respect to the solid angle. Always
+    /// zero for the specular materials, which are not lit by sampling the lights.
+    fn pdf(&self, _ray: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f32 {
+        0.0
+    }
+
+    /// Whether the light is only scattered in a few discrete directions, in which case the
+    /// lights are not worth sampling
+    fn is_specular(&self) -> bool {
+        true
+    }
 }
 
 /// Diffuse
 #[derive(Debug)]
 pub struct Lambertian {
     pub albedo: Arc<dyn Texture>,
+    id: usize,
 }
 
 impl Lambertian {
     pub fn new(albedo: Arc<dyn Texture>) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            id: next_id(),
+        }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)> {
-        let target = rec.p + rec.normal + Vec3::random_in_unit_sphere();
-        let attenuation = self.albedo.value(0.0, 0.0, &rec.p);
-        let scattered = Ray::new(rec.p, target - rec.p, ray.time());
-        Some((attenuation, scattered))
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // Cosine distributed, so the attenuation is just the albedo
+        let mut direction = rec.normal + Vec3::random_unit_vector(sampler);
+        if direction.squared_length() < 1e-8 {
+            direction = rec.normal;
+        }
+        Some(Scatter {
+            ray: Ray::new(rec.p, direction, ray.time()),
+            kind: ScatterKind::Diffuse,
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            pdf: Some(self.pdf(ray, rec, &direction)),
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(ray, rec, direction)
+    }
+
+    fn pdf(&self, _ray: &Ray, rec: &HitRecord, direction: &Vec3) -> f32 {
+        let cosine = rec.normal.dot(direction) / direction.length();
+        cosine.max(0.0) / PI
+    }
+
+    fn is_specular(&self) -> bool {
+        false
     }
 }
 
-/// Reflect
+/// Reflect, blurred by picking the direction in a ball of radius `fuzz` around the mirror
+/// direction
 #[derive(Debug)]
 pub struct Metal {
     pub albedo: Vec3,
     pub fuzz: f32,
+    id: usize,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3, f: f32) -> Self {
         let fuzz = f.min(1.0);
-        Self { albedo, fuzz }
+        Self {
+            albedo,
+            fuzz,
+            id: next_id(),
+        }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
         let reflected = ray.direction().unit_vector().reflect(&rec.normal);
-        let attenuation = self.albedo;
-        let scattered = Ray::new(
-            rec.p,
-            reflected + Vec3::random_in_unit_sphere() * self.fuzz,
-            ray.time(),
-        );
-
-        if scattered.direction().dot(&rec.normal) > 0.0 {
-            Some((attenuation, scattered))
+        let direction = reflected + Vec3::random_in_unit_sphere(sampler) * self.fuzz;
+
+        if direction.dot(&rec.normal) > 0.0 {
+            Some(Scatter {
+                ray: Ray::new(rec.p, direction, ray.time()),
+                kind: ScatterKind::Specular,
+                attenuation: self.albedo,
+                pdf: if self.is_specular() {
+                    None
+                } else {
+                    Some(self.pdf(ray, rec, &direction))
+                },
+            })
         } else {
             None
         }
     }
+
+    /// The directions going below the surface are absorbed, the others keep the albedo
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Vec3 {
+        if direction.dot(&rec.normal) > 0.0 {
+            self.albedo * self.pdf(ray, rec, direction)
+        } else {
+            Vec3::default()
+        }
+    }
+
+    /// The direction is `t w` for `t` between the two intersections `t0` and `t1` of the
+    /// line along `w` with the ball, the density being the volume `(t1^3 - t0^3) / 3` of
+    /// that part of the cone over the volume of the ball
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> f32 {
+        if self.is_specular() {
+            return 0.0;
+        }
+        let reflected = ray.direction().unit_vector().reflect(&rec.normal);
+        let b = direction.unit_vector().dot(&reflected);
+        let discriminant = b * b - 1.0 + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let root = discriminant.sqrt();
+        let t1 = b + root;
+        if t1 <= 0.0 {
+            return 0.0;
+        }
+        let t0 = (b - root).max(0.0);
+        // Factored to avoid the cancellation with a small fuzz
+        let cubes = (t1 - t0) * (t1 * t1 + t1 * t0 + t0 * t0);
+        cubes / (4.0 * PI * self.fuzz * self.fuzz * self.fuzz)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.fuzz <= 0.0
+    }
 }
 
 /// Refract
 #[derive(Debug)]
 pub struct Dielectric {
     pub ref_idx: f32,
+    id: usize,
 }
 
 impl Dielectric {
-    pub const fn new(ref_idx: f32) -> Self {
-        Self { ref_idx }
+    pub fn new(ref_idx: f32) -> Self {
+        Self {
+            ref_idx,
+            id: next_id(),
+        }
     }
 
     fn schlick(&self, cosine: f32) -> f32 {
@@ -83,9 +243,17 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Nothing is absorbed
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
         let reflected = ray.direction().reflect(&rec.normal);
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
         let (outward_normal, ni_over_nt, cosine) = if ray.direction().dot(&rec.normal) > 0.0 {
             (
                 -rec.normal,
@@ -99,16 +267,119 @@ impl Material for Dielectric {
                 -ray.direction().dot(&rec.normal) / ray.direction().length(),
             )
         };
-        if let Some(refracted) = ray.direction().refract(&outward_normal, ni_over_nt) {
-            if random() < self.schlick(cosine) {
-                let scattered = Ray::new(rec.p, reflected, ray.time());
-                Some((attenuation, scattered))
-            } else {
-                let scattered = Ray::new(rec.p, refracted, ray.time());
-                Some((attenuation, scattered))
+        // Everything is reflected beyond the critical angle
+        let (direction, kind) = match ray.direction().refract(&outward_normal, ni_over_nt) {
+            Some(refracted) if sampler.get_1d() >= self.schlick(cosine) => {
+                (refracted, ScatterKind::Transmission)
             }
-        } else {
-            Some((attenuation, Default::default()))
+            _ => (reflected, ScatterKind::Specular),
+        };
+        Some(Scatter {
+            ray: Ray::new(rec.p, direction, ray.time()),
+            kind,
+            attenuation: Vec3::new(1.0, 1.0, 1.0),
+            pdf: None,
+        })
+    }
+}
+
+/// Emit light
+#[derive(Debug)]
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture>,
+    id: usize,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Arc<dyn Texture>) -> Self {
+        Self {
+            emit,
+            id: next_id(),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The emitted light
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.emitted(rec.u, rec.v, &rec.p)
+    }
+
+    fn scatter(&self, _ray: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        self.emit.value(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// Scatter in a uniformly random direction, for the particles of a volume
+#[derive(Debug)]
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+    id: usize,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Arc<dyn Texture>) -> Self {
+        Self {
+            albedo,
+            id: next_id(),
         }
     }
 }
+
+impl Material for Isotropic {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        Some(Scatter {
+            ray: Ray::new(rec.p, Vec3::random_unit_vector(sampler), ray.time()),
+            kind: ScatterKind::Diffuse,
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            pdf: Some(1.0 / (4.0 * PI)),
+        })
+    }
+
+    fn eval(&self, _ray: &Ray, rec: &HitRecord, _direction: &Vec3) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _ray: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_in_creation_order() {
+        let first = Dielectric::new(1.5);
+        let second = Metal::new(Vec3::new(1.0, 1.0, 1.0), 0.0);
+        let third = Dielectric::new(1.5);
+        // Other tests create materials at the same time
+        assert!(first.id() < second.id());
+        assert!(second.id() < third.id());
+    }
+}
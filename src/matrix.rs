@@ -0,0 +1,197 @@
+use std::ops;
+
+use crate::vec3::Vec3;
+
+/// 4x4 matrix of an affine transformation, stored by rows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub data: [[f32; 4]; 4],
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Self = Self::new([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub const fn new(data: [[f32; 4]; 4]) -> Self {
+        Self { data }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        Self::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Counterclockwise rotation around the x axis, in degrees
+    pub fn rotation_x(angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Counterclockwise rotation around the y axis, in degrees
+    pub fn rotation_y(angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::new([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Counterclockwise rotation around the z axis, in degrees
+    pub fn rotation_z(angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::new([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = *self;
+        for (i, row) in result.data.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.data[j][i];
+            }
+        }
+        result
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting, `None` if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut m = self.data;
+        let mut inverse = Self::IDENTITY.data;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|a, b| {
+                    m[*a][column]
+                        .abs()
+                        .partial_cmp(&m[*b][column].abs())
+                        .unwrap()
+                })
+                .unwrap();
+            if m[pivot][column].abs() <= f32::EPSILON * f32::EPSILON {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = m[column][column].recip();
+            for j in 0..4 {
+                m[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+
+            for row in 0..4 {
+                let factor = m[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+
+        Some(Self::new(inverse))
+    }
+
+    /// Determinant of the linear part, how much volumes are scaled
+    pub fn linear_determinant(&self) -> f32 {
+        let m = &self.data;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Apply the whole transformation to a position, the last row being `(0, 0, 0, 1)`
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.data;
+        self.transform_vector(p) + Vec3::new(m[0][3], m[1][3], m[2][3])
+    }
+
+    /// Apply the transformation to a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.data;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl ops::Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut data = [[0.0; 4]; 4];
+        for (i, row) in data.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.data[i][k] * rhs.data[k][j]).sum();
+            }
+        }
+        Self::new(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Matrix4, b: &Matrix4) {
+        for (row_a, row_b) in a.data.iter().zip(b.data.iter()) {
+            for (x, y) in row_a.iter().zip(row_b.iter()) {
+                assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse() {
+        let matrix = Matrix4::translation(Vec3::new(265.0, -3.0, 295.0))
+            * Matrix4::rotation_x(30.0)
+            * Matrix4::rotation_y(-18.0)
+            * Matrix4::rotation_z(75.0)
+            * Matrix4::scaling(Vec3::new(2.0, 0.5, 3.0));
+        let inverse = matrix.inverse().unwrap();
+        assert_close(&(matrix * inverse), &Matrix4::IDENTITY);
+        assert_close(&(inverse * matrix), &Matrix4::IDENTITY);
+        assert!((matrix.linear_determinant() - 3.0).abs() < 1e-5);
+
+        let flat = Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(flat.inverse(), None);
+    }
+}
@@ -0,0 +1,104 @@
+use ::std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Isotropic, Material};
+use crate::random;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// Volume of constant density filling a closed boundary, such as smoke or fog
+#[derive(Debug)]
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f32,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f32, albedo: Arc<dyn Texture>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -density.recip(),
+            phase_function: Arc::new(Isotropic::new(albedo)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    /// The ray hits a particle after a random distance, if it is still inside the boundary
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // Where the ray enters and leaves the boundary, even behind its origin
+        let entry = self.boundary.hit(ray, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self.boundary.hit(ray, entry.t + 1e-4, f32::INFINITY)?;
+
+        let t_enter = entry.t.max(t_min);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let length = ray.direction().length();
+        let distance_inside = (t_exit - t_enter) * length;
+        // The sample drawn for the ray is hashed with where it enters the boundary, so that
+        // the distances travelled in two media along the same ray are independent
+        let bits = [ray.sample().to_bits() as u64, entry.t.to_bits() as u64];
+        let hit_distance =
+            self.neg_inv_density * random::linear_from_bits(random::hash(&bits)).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / length;
+        Some(HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            // Arbitrary, the phase function does not use it
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            material: self.phase_function.clone(),
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn transmittance() {
+        let white = Arc::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)));
+        let boundary = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(white.clone())),
+        );
+        let medium = ConstantMedium::new(Arc::new(boundary), 0.5, white);
+
+        // Through 2 units of the medium, the samples of the rays being spread over [0, 1)
+        let count = 10_000;
+        let passed = (0..count)
+            .filter(|&i| {
+                let sample = (i as f32 + 0.5) / count as f32;
+                let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0)
+                    .with_sample(sample);
+                medium.hit(&ray, 0.001, f32::MAX).is_none()
+            })
+            .count();
+        let transmittance = passed as f32 / count as f32;
+        assert!(
+            (transmittance - (-1.0f32).exp()).abs() < 0.02,
+            "{}",
+            transmittance
+        );
+    }
+}
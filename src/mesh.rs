@@ -0,0 +1,275 @@
+use ::std::fmt;
+use ::std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::bvh::BVH;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::triangle;
+use crate::vec3::Vec3;
+
+/// Buffers shared by all the triangles of a mesh
+#[derive(Debug)]
+struct MeshData {
+    positions: Vec<Vec3>,
+    /// Either empty or one per position
+    normals: Vec<Vec3>,
+    /// Either empty or one per position
+    uvs: Vec<(f32, f32)>,
+    indices: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl MeshData {
+    fn vertices(&self, index: usize) -> [Vec3; 3] {
+        let [i0, i1, i2] = self.indices[index];
+        [self.positions[i0], self.positions[i1], self.positions[i2]]
+    }
+}
+
+/// A triangle of a mesh, only holding its index
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+}
+
+impl fmt::Debug for MeshTriangle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MeshTriangle")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [v0, v1, v2] = self.mesh.vertices(self.index);
+        let (t, b1, b2) = triangle::intersect(v0, v1, v2, ray, t_min, t_max)?;
+        let normal = if self.mesh.normals.is_empty() {
+            (v1 - v0).cross(&(v2 - v0)).unit_vector()
+        } else {
+            let [i0, i1, i2] = self.mesh.indices[self.index];
+            let normals = [
+                self.mesh.normals[i0],
+                self.mesh.normals[i1],
+                self.mesh.normals[i2],
+            ];
+            triangle::interpolate(&normals, b1, b2).unit_vector()
+        };
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let [i0, i1, i2] = self.mesh.indices[self.index];
+            let uvs = [self.mesh.uvs[i0], self.mesh.uvs[i1], self.mesh.uvs[i2]];
+            triangle::interpolate_uv(&uvs, b1, b2)
+        };
+        Some(HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            normal,
+            u,
+            v,
+            material: self.mesh.material.clone(),
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(triangle::bounding_box(&self.mesh.vertices(self.index)))
+    }
+
+    fn is_light(&self) -> bool {
+        self.mesh.material.is_emissive()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(triangle::sample(&self.mesh.vertices(self.index), sampler))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, _time: f32) -> f32 {
+        let vertices = self.mesh.vertices(self.index);
+        triangle::pdf(&vertices, origin, direction, triangle::area(&vertices))
+    }
+}
+
+/// Indexed triangle mesh, with its own BVH over its triangles
+pub struct TriangleMesh {
+    mesh: Arc<MeshData>,
+    bvh: BVH<MeshTriangle>,
+    /// Area of the triangles up to each of them included, to pick them by area
+    cumulated_areas: Vec<f32>,
+}
+
+impl TriangleMesh {
+    /// `normals` and `uvs` are either empty or given for each position
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f32, f32)>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len());
+        assert!(uvs.is_empty() || uvs.len() == positions.len());
+        assert!(indices.iter().flatten().all(|&i| i < positions.len()));
+
+        let mesh = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+        });
+        let cumulated_areas = (0..mesh.indices.len())
+            .scan(0.0, |sum, index| {
+                *sum += triangle::area(&mesh.vertices(index));
+                Some(*sum)
+            })
+            .collect();
+        let triangles = (0..mesh.indices.len())
+            .map(|index| MeshTriangle {
+                mesh: Arc::clone(&mesh),
+                index,
+            })
+            .collect();
+        // Triangles do not move, any time window gives the same boxes
+        let bvh = BVH::new(triangles, 0.0, 0.0);
+
+        Self {
+            mesh,
+            bvh,
+            cumulated_areas,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.mesh.indices.len()
+    }
+
+    pub fn area(&self) -> f32 {
+        self.cumulated_areas.last().cloned().unwrap_or(0.0)
+    }
+}
+
+impl fmt::Debug for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TriangleMesh")
+            .field("vertices", &self.mesh.positions.len())
+            .field("triangles", &self.len())
+            .field("material", &self.mesh.material)
+            .finish()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.bvh.bounding_box(t0, t1)
+    }
+
+    fn is_light(&self) -> bool {
+        self.mesh.material.is_emissive() && self.area() > 0.0
+    }
+
+    /// On one of the triangles, picked in proportion to its area
+    fn sample_point(&self, _origin: &Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let target = sampler.get_1d() * self.area();
+        let index = self
+            .cumulated_areas
+            .partition_point(|&area| area <= target)
+            .min(self.len() - 1);
+        Some(triangle::sample(&self.mesh.vertices(index), sampler))
+    }
+
+    /// Mixture of the densities of the triangles weighted by their areas, which adds up the
+    /// triangles the direction goes through as parts of one surface
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, _time: f32) -> f32 {
+        let area = self.area();
+        let ray = Ray::new(*origin, *direction, 0.0);
+        let mut pdf = 0.0;
+        self.bvh
+            .for_each_candidate(&ray, 0.001, f32::MAX, |triangle: &MeshTriangle| {
+                let vertices = self.mesh.vertices(triangle.index);
+                pdf += triangle::pdf(&vertices, origin, direction, area);
+            });
+        pdf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::random::Generator;
+    use crate::rect::XYRect;
+    use crate::sampler::SamplerKind;
+    use crate::texture::ConstantTexture;
+
+    fn light() -> Arc<dyn Material> {
+        let white = ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0));
+        Arc::new(DiffuseLight::new(Arc::new(white)))
+    }
+
+    #[test]
+    fn same_density_as_a_rectangle() {
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let indices = vec![[0, 1, 2], [0, 2, 3]];
+        let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), indices, light());
+        let rect = XYRect::new(0.0, 2.0, 0.0, 1.0, 0.0, light());
+        assert!(mesh.is_light());
+
+        let origin = Vec3::new(0.3, 0.2, 2.0);
+        for &target in [(0.1, 0.1), (1.5, 0.5), (1.9, 0.9), (0.5, 0.8), (3.0, 0.5)].iter() {
+            let direction = Vec3::new(target.0, target.1, 0.0) - origin;
+            let expected = rect.pdf_value(&origin, &direction, 0.0);
+            let pdf = mesh.pdf_value(&origin, &direction, 0.0);
+            assert!(
+                (pdf - expected).abs() <= 1e-4 * expected,
+                "{} {}",
+                pdf,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn triangles_picked_by_area() {
+        // Areas of 1 and 3, far apart
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(13.0, 0.0, 0.0),
+            Vec3::new(10.0, 2.0, 0.0),
+        ];
+        let indices = vec![[0, 1, 2], [3, 4, 5]];
+        let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), indices, light());
+        assert_eq!(mesh.area(), 4.0);
+
+        let count = 10_000;
+        let mut sampler = SamplerKind::Stratified.build(1, count, Generator::Pcg32);
+        let mut first = 0;
+        for sample in 0..count {
+            sampler.start_sample(0, sample as u64);
+            let point = mesh
+                .sample_point(&Vec3::new(0.0, 0.0, 1.0), 0.0, &mut *sampler)
+                .unwrap();
+            assert_eq!(point.z(), 0.0);
+            if point.x() < 5.0 {
+                first += 1;
+            }
+        }
+        let fraction = first as f32 / count as f32;
+        assert!((fraction - 0.25).abs() < 0.01, "{}", fraction);
+    }
+}
@@ -4,6 +4,8 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::sphere::{sphere_uv, Sphere};
 use crate::vec3::Vec3;
 
 #[derive(Debug)]
@@ -40,6 +42,11 @@ impl MovingSphere {
             + (self.final_center - self.initial_center)
                 * ((time - self.initial_time) / (self.final_time - self.initial_time))
     }
+
+    /// The sphere where it is at `time`
+    fn at(&self, time: f32) -> Sphere {
+        Sphere::new(self.center(time), self.radius, self.material.clone())
+    }
 }
 
 impl Hittable for MovingSphere {
@@ -54,10 +61,14 @@ impl Hittable for MovingSphere {
             let mut temp = (-b - discriminant.sqrt()) / a;
             if temp < t_max && temp > t_min {
                 let p = ray.point_at_parameter(temp);
+                let normal = (p - self.center(ray.time())) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 let rec = HitRecord {
                     t: temp,
                     p,
-                    normal: (p - self.center(ray.time())) / self.radius,
+                    normal,
+                    u,
+                    v,
                     material: self.material.clone(),
                 };
                 return Some(rec);
@@ -65,10 +76,14 @@ impl Hittable for MovingSphere {
             temp = (-b + discriminant.sqrt()) / a;
             if temp < t_max && temp > t_min {
                 let p = ray.point_at_parameter(temp);
+                let normal = (p - self.center(ray.time())) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 let rec = HitRecord {
                     t: temp,
                     p,
-                    normal: (p - self.center(ray.time())) / self.radius,
+                    normal,
+                    u,
+                    v,
                     material: self.material.clone(),
                 };
                 return Some(rec);
@@ -80,7 +95,7 @@ impl Hittable for MovingSphere {
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         let box0 = AABB::new(
             self.center(t0) - Vec3::new(self.radius, self.radius, self.radius),
-            self.center(t1) + Vec3::new(self.radius, self.radius, self.radius),
+            self.center(t0) + Vec3::new(self.radius, self.radius, self.radius),
         );
         let box1 = AABB::new(
             self.center(t1) - Vec3::new(self.radius, self.radius, self.radius),
@@ -89,4 +104,17 @@ impl Hittable for MovingSphere {
         let aabb = AABB::surrounding_box(box0, box1);
         Some(aabb)
     }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    /// Sampled as the sphere it is at `time`, which is when the shadow ray is traced
+    fn sample_point(&self, origin: &Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        self.at(time).sample_point(origin, time, sampler)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        self.at(time).pdf_value(origin, direction, time)
+    }
 }
@@ -0,0 +1,500 @@
+//! Wavefront OBJ import, with the MTL materials it references.
+//!
+//! Faces are triangulated as fans, and each group (`g` or `o`) gets a mesh per material.
+//! MTL materials are mapped onto the materials of the raytracer:
+//! * transparent ones (`d` < 1, or `illum` 4, 6, 7 or 9) become `Dielectric` of index `Ni`,
+//! * reflective ones (`illum` 3, or `Ks` brighter than `Kd`) become `Metal` of color `Ks`,
+//!   the higher `Ns` the lower the fuzz,
+//! * the others become `Lambertian` of color `Kd`, or of texture `map_Kd`.
+
+use ::std::collections::HashMap;
+use ::std::fmt;
+use ::std::fs;
+use ::std::io;
+use ::std::path::{Path, PathBuf};
+use ::std::sync::Arc;
+
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::mesh::TriangleMesh;
+use crate::texture::{ConstantTexture, Filter, ImageTexture, Texture, Wrap};
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+type Result<T> = ::std::result::Result<T, ObjError>;
+
+/// Meshes of an OBJ file, `default_material` being used for faces without known material
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material>,
+) -> Result<Vec<TriangleMesh>> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let error = |line: usize, message: String| ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut materials = HashMap::new();
+    let mut material: Option<String> = None;
+    let mut group = String::new();
+    // Meshes in order of appearance, indexed by (group, material)
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut builder_indices: HashMap<(String, Option<String>), usize> = HashMap::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap().trim();
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&arguments).map_err(|e| error(line_number, e))?),
+            "vn" => normals.push(parse_vec3(&arguments).map_err(|e| error(line_number, e))?),
+            "vt" => {
+                let u = parse_float(arguments.first()).map_err(|e| error(line_number, e))?;
+                let v = match arguments.get(1) {
+                    Some(v) => parse_float(Some(v)).map_err(|e| error(line_number, e))?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(
+                        line_number,
+                        String::from("a face needs at least 3 vertices"),
+                    ));
+                }
+                let vertices = arguments
+                    .iter()
+                    .map(|vertex| {
+                        parse_face_vertex(vertex, positions.len(), uvs.len(), normals.len())
+                    })
+                    .collect::<::std::result::Result<Vec<_>, _>>()
+                    .map_err(|e| error(line_number, e))?;
+
+                let key = (group.clone(), material.clone());
+                let builder_index = *builder_indices.entry(key).or_insert_with(|| {
+                    builders.push(MeshBuilder::new(material.clone()));
+                    builders.len() - 1
+                });
+                let builder = &mut builders[builder_index];
+                let indices: Vec<usize> = vertices
+                    .iter()
+                    .map(|vertex| builder.vertex(*vertex, &positions, &uvs, &normals))
+                    .collect();
+                // Fan triangulation, exact for convex polygons
+                for i in 1..(indices.len() - 1) {
+                    builder
+                        .indices
+                        .push([indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+            "g" | "o" => group = arguments.join(" "),
+            "usemtl" => material = Some(arguments.join(" ")),
+            "mtllib" => {
+                for library in arguments {
+                    let library = path.with_file_name(library);
+                    match load_mtl(&library) {
+                        Ok(library) => materials.extend(library),
+                        Err(e) => eprintln!("warning: {}", e),
+                    }
+                }
+            }
+            // Smoothing groups, lines and points are not rendered
+            "s" | "l" | "p" => {}
+            _ => eprintln!(
+                "warning: {}:{}: unsupported statement `{}`",
+                path.display(),
+                line_number,
+                keyword
+            ),
+        }
+    }
+
+    Ok(builders
+        .into_iter()
+        .filter(|builder| !builder.indices.is_empty())
+        .map(|builder| {
+            let material = match &builder.material {
+                Some(name) => materials.get(name).cloned().unwrap_or_else(|| {
+                    eprintln!("warning: {}: unknown material `{}`", path.display(), name);
+                    Arc::clone(&default_material)
+                }),
+                None => Arc::clone(&default_material),
+            };
+            builder.build(material)
+        })
+        .collect())
+}
+
+/// Indices of position, texture coordinates and normal of a vertex of a face
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+#[derive(Debug)]
+struct MeshBuilder {
+    material: Option<String>,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+    indices: Vec<[usize; 3]>,
+    /// Whether every vertex so far has a normal, or texture coordinates
+    has_normals: bool,
+    has_uvs: bool,
+    vertices: HashMap<FaceVertex, usize>,
+}
+
+impl MeshBuilder {
+    fn new(material: Option<String>) -> Self {
+        Self {
+            material,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+            has_normals: true,
+            has_uvs: true,
+            vertices: HashMap::new(),
+        }
+    }
+
+    /// Index of the vertex in the mesh, OBJ indexing positions, normals and texture
+    /// coordinates separately while meshes share one index for all of them
+    fn vertex(
+        &mut self,
+        vertex: FaceVertex,
+        positions: &[Vec3],
+        uvs: &[(f32, f32)],
+        normals: &[Vec3],
+    ) -> usize {
+        if let Some(index) = self.vertices.get(&vertex) {
+            return *index;
+        }
+
+        let (position, uv, normal) = vertex;
+        self.positions.push(positions[position]);
+        self.uvs.push(uv.map_or((0.0, 0.0), |uv| uvs[uv]));
+        self.normals
+            .push(normal.map_or_else(Vec3::default, |normal| normals[normal]));
+        self.has_uvs &= uv.is_some();
+        self.has_normals &= normal.is_some();
+
+        let index = self.positions.len() - 1;
+        self.vertices.insert(vertex, index);
+        index
+    }
+
+    fn build(mut self, material: Arc<dyn Material>) -> TriangleMesh {
+        if !self.has_normals {
+            self.normals.clear();
+        }
+        if !self.has_uvs {
+            self.uvs.clear();
+        }
+        TriangleMesh::new(
+            self.positions,
+            self.normals,
+            self.uvs,
+            self.indices,
+            material,
+        )
+    }
+}
+
+fn parse_float(word: Option<&&str>) -> ::std::result::Result<f32, String> {
+    let word = word.ok_or_else(|| String::from("missing number"))?;
+    word.parse()
+        .map_err(|_| format!("invalid number `{}`", word))
+}
+
+fn parse_vec3(arguments: &[&str]) -> ::std::result::Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse_float(arguments.first())?,
+        parse_float(arguments.get(1))?,
+        parse_float(arguments.get(2))?,
+    ))
+}
+
+/// `v`, `v/vt`, `v//vn` or `v/vt/vn`, indices starting at 1 or counted from the end if negative
+fn parse_face_vertex(
+    word: &str,
+    nb_positions: usize,
+    nb_uvs: usize,
+    nb_normals: usize,
+) -> ::std::result::Result<FaceVertex, String> {
+    let index = |word: &str, len: usize| -> ::std::result::Result<usize, String> {
+        let index: i64 = word
+            .parse()
+            .map_err(|_| format!("invalid index `{}`", word))?;
+        let resolved = if index < 0 {
+            len as i64 + index
+        } else {
+            index - 1
+        };
+        if resolved < 0 || resolved >= len as i64 {
+            Err(format!("index `{}` is out of bounds", index))
+        } else {
+            Ok(resolved as usize)
+        }
+    };
+
+    let mut parts = word.split('/');
+    let position = index(parts.next().unwrap(), nb_positions)?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(word) => Some(index(word, nb_uvs)?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(word) => Some(index(word, nb_normals)?),
+    };
+    Ok((position, uv, normal))
+}
+
+#[derive(Debug)]
+struct MtlMaterial {
+    diffuse: Vec3,
+    specular: Vec3,
+    shininess: f32,
+    ref_idx: f32,
+    dissolve: f32,
+    illum: Option<u32>,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: Vec3::default(),
+            shininess: 0.0,
+            ref_idx: 1.5,
+            dissolve: 1.0,
+            illum: None,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn build(&self, path: &Path) -> Arc<dyn Material> {
+        let luminance = |color: Vec3| 0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b();
+
+        if self.dissolve < 1.0 || matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9)) {
+            Arc::new(Dielectric::new(self.ref_idx))
+        } else if self.illum == Some(3) || luminance(self.specular) > luminance(self.diffuse) {
+            // Same width of the specular lobe as a Phong exponent
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            Arc::new(Metal::new(self.specular, fuzz))
+        } else {
+            let texture: Arc<dyn Texture> = match &self.diffuse_map {
+                Some(map) => match ImageTexture::load(map, Filter::Bilinear, Wrap::Repeat) {
+                    Ok(texture) => Arc::new(texture),
+                    Err(e) => {
+                        eprintln!(
+                            "warning: {}: cannot load {}: {}",
+                            path.display(),
+                            map.display(),
+                            e
+                        );
+                        Arc::new(ConstantTexture::new(self.diffuse))
+                    }
+                },
+                None => Arc::new(ConstantTexture::new(self.diffuse)),
+            };
+            Arc::new(Lambertian::new(texture))
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let error = |line: usize, message: String| ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap().trim();
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.build(path));
+            }
+            current = Some((arguments.join(" "), MtlMaterial::default()));
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None => {
+                return Err(error(
+                    line_number,
+                    format!("`{}` before any `newmtl`", keyword),
+                ))
+            }
+        };
+        let float = |i: usize| parse_float(arguments.get(i)).map_err(|e| error(line_number, e));
+
+        match keyword {
+            "Kd" => material.diffuse = parse_vec3(&arguments).map_err(|e| error(line_number, e))?,
+            "Ks" => {
+                material.specular = parse_vec3(&arguments).map_err(|e| error(line_number, e))?
+            }
+            "Ns" => material.shininess = float(0)?,
+            "Ni" => material.ref_idx = float(0)?,
+            "d" => material.dissolve = float(0)?,
+            "Tr" => material.dissolve = 1.0 - float(0)?,
+            "illum" => material.illum = Some(float(0)? as u32),
+            // Options such as `-s 1 1 1` come before the file name
+            "map_Kd" => material.diffuse_map = arguments.last().map(|map| path.with_file_name(map)),
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material.build(path));
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use ::std::env;
+    use ::std::process;
+
+    fn temporary(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("raytracer-{}-{}", process::id(), name))
+    }
+
+    fn default_material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(
+            0.5, 0.5, 0.5,
+        )))))
+    }
+
+    /// Meshes of `source` written to a file, along with the path of that file
+    fn load_source(
+        name: &str,
+        source: &str,
+        default_material: Arc<dyn Material>,
+    ) -> (PathBuf, Result<Vec<TriangleMesh>>) {
+        let path = temporary(name);
+        fs::write(&path, source).unwrap();
+        let meshes = load(&path, default_material);
+        fs::remove_file(&path).unwrap();
+        (path, meshes)
+    }
+
+    #[test]
+    fn negative_indices() {
+        let source = "
+            mtllib missing.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 1
+            usemtl unknown
+            f -4/-4/-1 -3/-3/-1 -2/-2/-1 -1/-1/-1
+        ";
+        let default_material = default_material();
+        let (_, meshes) = load_source("negative.obj", source, Arc::clone(&default_material));
+        let meshes = meshes.unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(mesh.area(), 1.0);
+
+        let ray = Ray::new(Vec3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = mesh.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.75).abs() < 1e-6);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        // Neither the library nor the material exist
+        assert_eq!(rec.material.id(), default_material.id());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let (_, meshes) = load_source(
+            "bounds.obj",
+            "v 0 0 0\nv 1 0 0\n\nf 1 2 5\n",
+            default_material(),
+        );
+        match meshes.unwrap_err() {
+            ObjError::Parse { line, message, .. } => {
+                assert_eq!(line, 4);
+                assert_eq!(message, "index `5` is out of bounds");
+            }
+            e => panic!("{}", e),
+        }
+
+        let (path, meshes) = load_source("number.obj", "v 0 0 0\nv 1 zero 0\n", default_material());
+        let expected = format!("{}:2: invalid number `zero`", path.display());
+        assert_eq!(meshes.unwrap_err().to_string(), expected);
+
+        let (_, meshes) = load_source("face.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n", default_material());
+        match meshes.unwrap_err() {
+            ObjError::Parse { line, message, .. } => {
+                assert_eq!(line, 3);
+                assert_eq!(message, "a face needs at least 3 vertices");
+            }
+            e => panic!("{}", e),
+        }
+
+        let path = temporary("missing.obj");
+        match load(&path, default_material()) {
+            Err(ObjError::Io(error_path, _)) => assert_eq!(error_path, path),
+            _ => panic!("{} should not exist", path.display()),
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! Writing the film to disk, either converted for display in the usual 8-bit formats or as
+//! linear radiance in OpenEXR, Radiance HDR or PFM.
+
+use ::std::fmt;
+use ::std::fs::File;
+use ::std::io::{self, BufWriter, Write};
+use ::std::path::{Path, PathBuf};
+
+use image::hdr::HDREncoder;
+use image::pnm::{PNMEncoder, PNMSubtype, SampleEncoding};
+use image::{ColorType, ImageBuffer, ImageFormat, Rgb};
+
+use crate::config::Config;
+use crate::film::Film;
+use crate::tonemap::ToneMap;
+use crate::vec3::Vec3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8 bits per channel, tone mapped
+    Image(ImageFormat),
+    /// OpenEXR, uncompressed scanlines of halves or floats
+    Exr,
+    /// Radiance RGBE
+    Hdr,
+    /// Portable float map
+    Pfm,
+}
+
+impl OutputFormat {
+    /// Only the formats which can be written are accepted
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "png" => Some(OutputFormat::Image(ImageFormat::Png)),
+            "jpg" | "jpeg" => Some(OutputFormat::Image(ImageFormat::Jpeg)),
+            "bmp" => Some(OutputFormat::Image(ImageFormat::Bmp)),
+            "tif" | "tiff" => Some(OutputFormat::Image(ImageFormat::Tiff)),
+            "pnm" | "ppm" => Some(OutputFormat::Image(ImageFormat::Pnm)),
+            "ico" => Some(OutputFormat::Image(ImageFormat::Ico)),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    /// Guessed from the extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str().and_then(Self::from_name)
+    }
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    /// The format could not be guessed from this path
+    UnknownFormat(PathBuf),
+    Io(io::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputError::UnknownFormat(path) => write!(
+                f,
+                "unknown format for {}, use `--format` or a known extension",
+                path.display()
+            ),
+            OutputError::Io(e) => write!(f, "{}", e),
+            OutputError::Image(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for OutputError {
+    fn from(e: io::Error) -> Self {
+        OutputError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for OutputError {
+    fn from(e: image::ImageError) -> Self {
+        OutputError::Image(e)
+    }
+}
+
+/// Save the film in `config.output`, `tone_map` being only used by the 8-bit formats
+pub fn save(film: &Film, config: &Config, tone_map: &ToneMap) -> Result<(), OutputError> {
+    let format = config.output_format()?;
+    let (width, height) = (film.width, film.height);
+
+    match format {
+        OutputFormat::Image(format) => {
+            let bytes = tone_map
+                .apply(film)
+                .into_iter()
+                .flatten()
+                .collect::<Vec<u8>>();
+            if format == ImageFormat::Pnm {
+                // Not handled by `save_with_format`
+                let mut writer = BufWriter::new(File::create(&config.output)?);
+                PNMEncoder::new(&mut writer)
+                    .with_subtype(PNMSubtype::Pixmap(SampleEncoding::Binary))
+                    .encode(&bytes[..], width as u32, height as u32, ColorType::Rgb8)?;
+                writer.flush()?;
+            } else {
+                ImageBuffer::<Rgb<u8>, _>::from_vec(width as u32, height as u32, bytes)
+                    .expect("The buffer has the size of the image")
+                    .save_with_format(&config.output, format)?;
+            }
+        }
+        OutputFormat::Exr => {
+            let mut layers = vec![("", film.colors().collect::<Vec<_>>())];
+            if config.exr_variance {
+                layers.push(("variance", film.variances().collect()));
+            }
+            let mut writer = BufWriter::new(File::create(&config.output)?);
+            write_exr(&mut writer, width, height, &layers, config.exr_float)?;
+            writer.flush()?;
+        }
+        OutputFormat::Hdr => {
+            // RGBE can only store positive values
+            let channel = |c: f32| if c > 0.0 { c } else { 0.0 };
+            let pixels = film
+                .colors()
+                .map(|c| Rgb([channel(c.r()), channel(c.g()), channel(c.b())]))
+                .collect::<Vec<_>>();
+            let writer = BufWriter::new(File::create(&config.output)?);
+            HDREncoder::new(writer).encode(&pixels, width, height)?;
+        }
+        OutputFormat::Pfm => {
+            let mut writer = BufWriter::new(File::create(&config.output)?);
+            write_pfm(
+                &mut writer,
+                width,
+                height,
+                &film.colors().collect::<Vec<_>>(),
+            )?;
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Portable float map, little endian as told by the negative scale, the rows going upwards
+fn write_pfm<W: Write>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    pixels: &[Vec3],
+) -> io::Result<()> {
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in pixels.chunks(width).rev() {
+        for pixel in row {
+            for channel in pixel.data.iter() {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Single part scanline OpenEXR file, without compression. Each layer is stored as its
+/// `R`, `G` and `B` channels, prefixed by the name of the layer unless it is empty.
+fn write_exr<W: Write>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    layers: &[(&str, Vec<Vec3>)],
+    float: bool,
+) -> io::Result<()> {
+    // Name, layer and index in the pixel, readers expect the channels to be sorted by name
+    let mut channels = Vec::new();
+    for (layer, (name, _)) in layers.iter().enumerate() {
+        for (index, channel) in ["R", "G", "B"].iter().enumerate() {
+            let name = if name.is_empty() {
+                channel.to_string()
+            } else {
+                format!("{}.{}", name, channel)
+            };
+            channels.push((name, layer, index));
+        }
+    }
+    channels.sort();
+
+    let (pixel_type, channel_size) = if float { (2i32, 4) } else { (1i32, 2) };
+
+    let mut channel_list = Vec::new();
+    for (name, _, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&pixel_type.to_le_bytes());
+        // Not perceptually linear, then 3 reserved bytes
+        channel_list.extend_from_slice(&[0; 4]);
+        // No subsampling
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let window = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|x: &i32| x.to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+
+    let mut header = Vec::new();
+    // Magic number, then version 2 without any flag
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        for string in &[name, kind] {
+            header.extend_from_slice(string.as_bytes());
+            header.push(0);
+        }
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    attribute("channels", "chlist", &channel_list);
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    // Increasing y
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // One scanline per block, each one starting with its y and the size of its data
+    let data_size = width * channels.len() * channel_size;
+    let first_block = header.len() + height * 8;
+    for y in 0..height {
+        let offset = first_block + y * (8 + data_size);
+        writer.write_all(&(offset as u64).to_le_bytes())?;
+    }
+
+    let mut block = Vec::with_capacity(data_size);
+    for y in 0..height {
+        block.clear();
+        for &(_, layer, index) in &channels {
+            for pixel in &layers[layer].1[y * width..(y + 1) * width] {
+                let value = pixel[index];
+                if float {
+                    block.extend_from_slice(&value.to_le_bytes());
+                } else {
+                    block.extend_from_slice(&to_half(value).to_le_bytes());
+                }
+            }
+        }
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(data_size as i32).to_le_bytes())?;
+        writer.write_all(&block)?;
+    }
+
+    Ok(())
+}
+
+/// Nearest half precision float, ties rounding to even
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity, or a NaN which must keep a non zero mantissa
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        // Too big, rounded to infinity
+        return sign | 0x7c00;
+    }
+
+    let (half, rest, halfway) = if exponent <= 0 {
+        if exponent < -10 {
+            // Less than half of the smallest subnormal
+            return sign;
+        }
+        // Subnormal, the implicit leading bit becomes explicit
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (
+            mantissa >> shift,
+            mantissa & ((1 << shift) - 1),
+            1 << (shift - 1),
+        )
+    } else {
+        (
+            ((exponent as u32) << 10) | (mantissa >> 13),
+            mantissa & 0x1fff,
+            0x1000,
+        )
+    };
+
+    // A carry in the exponent is right, even when it rounds up to infinity
+    let round = rest > halfway || (rest == halfway && half & 1 == 1);
+    sign | (half + round as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::env;
+    use ::std::fs;
+    use ::std::process;
+
+    use image::hdr::HdrDecoder;
+
+    use super::*;
+    use crate::filter::{Filter, FilterKind};
+
+    #[test]
+    fn half_known_answers() {
+        assert_eq!(to_half(0.0), 0x0000);
+        assert_eq!(to_half(-0.0), 0x8000);
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+        assert_eq!(to_half(0.5), 0x3800);
+        // Largest finite half
+        assert_eq!(to_half(65504.0), 0x7bff);
+    }
+
+    #[test]
+    fn half_subnormals() {
+        let smallest = 2f32.powi(-24);
+        assert_eq!(to_half(2f32.powi(-14)), 0x0400);
+        assert_eq!(to_half(smallest), 0x0001);
+        assert_eq!(to_half(1023.0 * smallest), 0x03ff);
+        assert_eq!(to_half(-smallest), 0x8001);
+        // Halfway between 0 and the smallest subnormal, and below
+        assert_eq!(to_half(smallest / 2.0), 0x0000);
+        assert_eq!(to_half(smallest / 4.0), 0x0000);
+        assert_eq!(to_half(smallest * 0.75), 0x0001);
+        assert_eq!(to_half(smallest * 1.5), 0x0002);
+        assert_eq!(to_half(smallest * 2.5), 0x0002);
+    }
+
+    #[test]
+    fn half_rounds_to_nearest_even() {
+        let ulp = 2f32.powi(-10);
+        assert_eq!(to_half(1.0 + ulp / 2.0), 0x3c00);
+        assert_eq!(to_half(1.0 + ulp * 1.5), 0x3c02);
+        assert_eq!(to_half(1.0 + ulp / 2.0 + ulp / 1024.0), 0x3c01);
+        assert_eq!(to_half(1.0 + ulp / 2.0 - ulp / 1024.0), 0x3c00);
+        // The rounding carries into the exponent
+        assert_eq!(to_half(2.0 - ulp / 4.0), 0x4000);
+    }
+
+    #[test]
+    fn half_overflow_and_nan() {
+        assert_eq!(to_half(65519.0), 0x7bff);
+        // Halfway between the largest half and the next power of two
+        assert_eq!(to_half(65520.0), 0x7c00);
+        assert_eq!(to_half(1e10), 0x7c00);
+        assert_eq!(to_half(-1e10), 0xfc00);
+        assert_eq!(to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(to_half(f32::NEG_INFINITY), 0xfc00);
+        let nan = to_half(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x03ff, 0);
+    }
+
+    fn read_u64(bytes: &[u8], at: usize) -> u64 {
+        let mut word = [0; 8];
+        word.copy_from_slice(&bytes[at..at + 8]);
+        u64::from_le_bytes(word)
+    }
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[at..at + 4]);
+        i32::from_le_bytes(word)
+    }
+
+    #[test]
+    fn exr_header_and_offsets() {
+        let pixels = vec![
+            Vec3::new(1.0, 2.0, 0.5),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 65504.0),
+        ];
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, 2, 2, &[("", pixels)], false).unwrap();
+
+        assert_eq!(&bytes[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        let channels = b"channels\0chlist\0";
+        assert_eq!(&bytes[8..8 + channels.len()], channels);
+        // Sorted by name
+        let list = 8 + channels.len() + 4;
+        assert_eq!(read_i32(&bytes, list - 4), 3 * 18 + 1);
+        assert_eq!(&bytes[list..list + 2], b"B\0");
+        assert_eq!(&bytes[list + 18..list + 20], b"G\0");
+        assert_eq!(&bytes[list + 36..list + 38], b"R\0");
+        // Half
+        assert_eq!(read_i32(&bytes, list + 2), 1);
+
+        // The header ends with an empty name right before the offsets, each block holding
+        // its y, its size and 2 pixels of 3 halves
+        let header_size = 313;
+        assert_eq!(bytes[header_size - 1], 0);
+        let block_size = 8 + 2 * 3 * 2;
+        let first_block = header_size + 2 * 8;
+        assert_eq!(read_u64(&bytes, header_size), first_block as u64);
+        assert_eq!(
+            read_u64(&bytes, header_size + 8),
+            (first_block + block_size) as u64
+        );
+        assert_eq!(bytes.len(), first_block + 2 * block_size);
+
+        let second_block = first_block + block_size;
+        assert_eq!(read_i32(&bytes, second_block), 1);
+        assert_eq!(read_i32(&bytes, second_block + 4), 12);
+        // The B channel of the row, then G and R
+        let halves = bytes[second_block + 8..]
+            .chunks(2)
+            .map(|half| u16::from_le_bytes([half[0], half[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(halves, [0x0000, 0x7bff, 0x0000, 0x0000, 0xbc00, 0x0000]);
+    }
+
+    #[test]
+    fn pfm_header_and_byte_order() {
+        let pixels = [
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(4.0, 5.0, 6.0),
+            Vec3::new(7.0, 8.0, 9.0),
+            Vec3::new(10.0, 11.0, 12.0),
+        ];
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, 2, 2, &pixels).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let data = &bytes[header.len()..];
+        assert_eq!(data.len(), 4 * 3 * 4);
+        // Little endian, from the bottom row up
+        assert_eq!(&data[..4], &7f32.to_le_bytes());
+        assert_eq!(&data[..4], &[0x00, 0x00, 0xe0, 0x40]);
+        let values = data
+            .chunks(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        );
+    }
+
+    /// Film with one sample of each color, row by row from the top
+    fn film(width: usize, height: usize, colors: &[Vec3]) -> Film {
+        let filter = Filter::new(FilterKind::Box, None);
+        let mut film = Film::new(width, height);
+        for (index, color) in colors.iter().enumerate() {
+            let (x, y) = ((index % width) as f32 + 0.5, (index / width) as f32 + 0.5);
+            film.add_sample(x, y, *color, &filter);
+        }
+        film
+    }
+
+    /// Path in the temporary directory, not shared with other processes
+    fn temporary(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("raytracer-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn save_hdr() {
+        let path = temporary("save.hdr");
+        let args = vec!["--output".to_string(), path.display().to_string()];
+        let config = Config::from_args(args).unwrap();
+        let colors = [
+            Vec3::new(1.0, 0.5, 0.25),
+            Vec3::new(100.0, 0.0, 3.0),
+            Vec3::new(-1.0, 0.125, 2.0),
+            Vec3::new(0.0, 0.0, 0.0),
+        ];
+        save(&film(2, 2, &colors), &config, &config.tone_map).unwrap();
+
+        let file = io::BufReader::new(File::open(&path).unwrap());
+        let decoder = HdrDecoder::new(file).unwrap();
+        let metadata = decoder.metadata();
+        assert_eq!((metadata.width, metadata.height), (2, 2));
+        let pixels = decoder.read_image_hdr().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // RGBE keeps 8 bits of mantissa for the largest channel, and no negative value
+        let expected = [
+            [1.0, 0.5, 0.25],
+            [100.0, 0.0, 3.0],
+            [0.0, 0.125, 2.0],
+            [0.0, 0.0, 0.0],
+        ];
+        for (pixel, expected) in pixels.iter().zip(expected.iter()) {
+            let largest = expected.iter().cloned().fold(0.0, f32::max);
+            for channel in 0..3 {
+                let error = (pixel[channel] - expected[channel]).abs();
+                assert!(error <= largest / 128.0, "{:?} for {:?}", pixel, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_extension() {
+        let path = temporary("save.xyz");
+        let args = vec!["--output".to_string(), path.display().to_string()];
+        let config = Config::from_args(args).unwrap();
+        match save(&film(1, 1, &[Vec3::default()]), &config, &config.tone_map) {
+            Err(OutputError::UnknownFormat(unknown)) => assert_eq!(unknown, path),
+            result => panic!("{:?}", result),
+        }
+        assert!(!path.exists());
+    }
+}
@@ -1,9 +1,10 @@
-use ::std::ptr::swap;
-use ::std::sync::Once;
+use ::std::sync::OnceLock;
 
-use crate::random::random;
+use crate::random::{Rng, Xoshiro256Plus};
 use crate::vec3::Vec3;
 
+const PERLIN_SEED: u64 = 0x5EED;
+
 #[derive(Debug)]
 pub struct Perlin {
     random_vec: &'static [Vec3],
@@ -14,28 +15,24 @@ pub struct Perlin {
 
 impl Perlin {
     pub fn new() -> Self {
-        static mut RANDOM_VEC: Option<[Vec3; 256]> = None;
-        static mut PERM_X: Option<[i32; 256]> = None;
-        static mut PERM_Y: Option<[i32; 256]> = None;
-        static mut PERM_Z: Option<[i32; 256]> = None;
-
-        static ONCE: Once = Once::new();
-        ONCE.call_once(|| {
-            unsafe {
-                RANDOM_VEC = Some(Self::perlin_generate());
-                PERM_X = Some(Self::perlin_generate_perm());
-                PERM_Y = Some(Self::perlin_generate_perm());
-                PERM_Z = Some(Self::perlin_generate_perm());
-            }
+        type Tables = ([Vec3; 256], [i32; 256], [i32; 256], [i32; 256]);
+        static TABLES: OnceLock<Tables> = OnceLock::new();
+
+        // Always the same noise, whatever the seed of the render
+        let (random_vec, perm_x, perm_y, perm_z) = TABLES.get_or_init(|| {
+            let mut rng = Xoshiro256Plus::from_seed(PERLIN_SEED);
+            (
+                Self::perlin_generate(&mut rng),
+                Self::perlin_generate_perm(&mut rng),
+                Self::perlin_generate_perm(&mut rng),
+                Self::perlin_generate_perm(&mut rng),
+            )
         });
-
-        unsafe {
-            Self {
-                random_vec: RANDOM_VEC.as_ref().unwrap(),
-                perm_x: PERM_X.as_ref().unwrap(),
-                perm_y: PERM_Y.as_ref().unwrap(),
-                perm_z: PERM_Z.as_ref().unwrap(),
-            }
+        Self {
+            random_vec,
+            perm_x,
+            perm_y,
+            perm_z,
         }
     }
 
@@ -65,39 +62,37 @@ impl Perlin {
         Self::perlin_interpolation(&c, u, v, w)
     }
 
-    fn perlin_generate() -> [Vec3; 256] {
+    fn perlin_generate(rng: &mut dyn Rng) -> [Vec3; 256] {
         let mut p = [Default::default(); 256];
-        for i in 0..256 {
-            p[i] = Vec3::new(
-                2.0 * random() - 1.0,
-                2.0 * random() - 1.0,
-                2.0 * random() - 1.0,
+        for vec in p.iter_mut() {
+            *vec = Vec3::new(
+                2.0 * rng.linear() - 1.0,
+                2.0 * rng.linear() - 1.0,
+                2.0 * rng.linear() - 1.0,
             )
             .unit_vector();
         }
         p
     }
 
-    fn permute(p: &mut [i32]) {
+    fn permute(p: &mut [i32], rng: &mut dyn Rng) {
         for i in (0..p.len()).rev() {
-            let target = (random() * (i as f32 + 1.0)) as usize;
-            unsafe {
-                swap(&mut p[i], &mut p[target]);
-            }
+            let target = (rng.linear() * (i as f32 + 1.0)) as usize;
+            p.swap(i, target);
         }
     }
 
-    fn perlin_generate_perm() -> [i32; 256] {
-        let mut p =  [0; 256];
-        for i in 0..256 {
-            p[i] = i as i32;
+    fn perlin_generate_perm(rng: &mut dyn Rng) -> [i32; 256] {
+        let mut p = [0; 256];
+        for (i, value) in p.iter_mut().enumerate() {
+            *value = i as i32;
         }
-        Self::permute(&mut p);
+        Self::permute(&mut p, rng);
         p
     }
 
     #[inline]
-    #[allow(dead_code)]
+    #[allow(dead_code, clippy::needless_range_loop)]
     fn trilinear_interp(c: &[[[f32; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
         let mut accum = 0.0;
         for i in 0..2 {
@@ -114,6 +109,7 @@ impl Perlin {
     }
 
     #[inline]
+    #[allow(clippy::needless_range_loop)]
     fn perlin_interpolation(c: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
         let uu = u * u * (3.0 - 2.0 * u);
         let vv = v * v * (3.0 - 2.0 * v);
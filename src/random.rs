@@ -1,36 +1,97 @@
-use ::std::{
-    f32,
-    sync::{Arc, Mutex},
-    time::{SystemTime, UNIX_EPOCH},
-};
-
-/// Implement Xoshiro256+ algorithm
-pub struct XorShift32 {
-    state: [u64; 4],
+use ::std::f32;
+use ::std::str::FromStr;
+
+/// Source of uniform random numbers. Each sample owns its generator, so that renders only
+/// depend on the seed, not on the number of workers nor on the order of the chunks.
+pub trait Rng {
+    fn from_seed(seed: u64) -> Self
+    where
+        Self: Sized;
+
+    fn next_u64(&mut self) -> u64;
+
+    /// Uniform in `[0, 1)`
+    fn linear(&mut self) -> f32 {
+        linear_from_bits(self.next_u64())
+    }
+
+    /// Standard normal distribution, with the Box-Muller transform
+    #[allow(dead_code)]
+    fn normal(&mut self) -> f32 {
+        // In (0, 1] so the logarithm is finite
+        let u1 = 1.0 - self.linear();
+        let u2 = self.linear();
+
+        (-2.0 * u1.ln()).sqrt() * (2.0 * f32::consts::PI * u2).cos()
+    }
 }
 
-impl XorShift32 {
-    pub fn new(seed: Option<u64>) -> Self {
-        let mut seed = seed.unwrap_or_else(|| {
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        });
-        let mut state = [0; 4];
+/// Generator of one sample of one pixel, independent from the other samples
+pub fn for_sample<R: Rng>(seed: u64, pixel: u64, sample: u64) -> R {
+    R::from_seed(hash(&[seed, pixel, sample]))
+}
+
+/// Uniform in `[0, 1)`, from the 24 highest bits which all fit in the mantissa
+pub fn linear_from_bits(bits: u64) -> f32 {
+    (bits >> 40) as f32 * (1.0 / (1u64 << 24) as f32)
+}
+
+/// The generators which can be chosen for a render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Generator {
+    #[default]
+    Xoshiro256Plus,
+    Pcg32,
+}
+
+impl Generator {
+    pub fn seeded(self, seed: u64) -> Box<dyn Rng> {
+        match self {
+            Generator::Xoshiro256Plus => Box::new(Xoshiro256Plus::from_seed(seed)),
+            Generator::Pcg32 => Box::new(Pcg32::from_seed(seed)),
+        }
+    }
+}
 
-        let mut tmp = Self::split_mix_64(&mut seed);
-        state[0] = tmp;
-        state[1] = tmp >> 32;
+impl FromStr for Generator {
+    type Err = ();
 
-        tmp = Self::split_mix_64(&mut seed);
-        state[2] = tmp;
-        state[3] = tmp >> 32;
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "xoshiro" | "xoshiro256+" => Ok(Generator::Xoshiro256Plus),
+            "pcg" | "pcg32" => Ok(Generator::Pcg32),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Xoshiro256+ from Blackman and Vigna, its lowest bits being weak only the highest are used
+#[derive(Debug, Clone)]
+pub struct Xoshiro256Plus {
+    state: [u64; 4],
+}
+
+impl Xoshiro256Plus {
+    /// Advance the SplitMix64 generator `s`, which is used to fill the state from one word
+    fn split_mix_64(s: &mut u64) -> u64 {
+        *s = s.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut result = *s;
+        result = (result ^ (result >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        result = (result ^ (result >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        result ^ (result >> 31)
+    }
+}
 
+impl Rng for Xoshiro256Plus {
+    fn from_seed(mut seed: u64) -> Self {
+        let mut state = [0; 4];
+        for word in state.iter_mut() {
+            *word = Self::split_mix_64(&mut seed);
+        }
         Self { state }
     }
 
-    pub fn linear(&mut self) -> f32 {
+    fn next_u64(&mut self) -> u64 {
         let result = self.state[0].wrapping_add(self.state[3]);
         let t = self.state[1] << 17;
 
@@ -40,62 +101,166 @@ impl XorShift32 {
         self.state[0] ^= self.state[3];
 
         self.state[2] ^= t;
-        self.state[3] = Self::rol_64(self.state[3], 45);
+        self.state[3] = self.state[3].rotate_left(45);
 
-        (result as u64 >> 3) as f32 / (2u64.pow(61) - 1) as f32
+        result
     }
+}
 
-    fn rol_64(x: u64, k: u64) -> u64 {
-        (x << k) | (x >> (64 - k))
-    }
+/// PCG-XSH-RR from O'Neill, with 64 bits of state and 32 bits of output
+#[derive(Debug, Clone)]
+pub struct Pcg32 {
+    state: u64,
+    /// Selects the stream, always odd
+    increment: u64,
+}
 
-    fn split_mix_64(s: &mut u64) -> u64 {
-        let mut result = *s;
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
 
-        *s = result.wrapping_div(0x9E37_79B9_7F4A_7C15);
-        result = (result ^ (result >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
-        result = (result ^ (result >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
-        result ^ (result >> 31)
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut pcg = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        pcg.next_u32();
+        pcg.state = pcg.state.wrapping_add(seed);
+        pcg.next_u32();
+        pcg
     }
 
-    #[allow(dead_code)]
-    pub fn normal(&mut self) -> f32 {
-        let u1 = self.linear();
-        let u2 = self.linear();
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xor_shifted = (((old >> 18) ^ old) >> 27) as u32;
+        xor_shifted.rotate_right((old >> 59) as u32)
+    }
+}
 
-        let z0 = (-2.0 * u1.ln()).sqrt() * (2.0 * f32::consts::PI * u2).cos();
-        // let z1 = (-2.0 * u1.ln()).sqrt() * (2.0 * f32::consts::PI * u2).sin();
+impl Rng for Pcg32 {
+    fn from_seed(seed: u64) -> Self {
+        // Neighbour seeds also get unrelated streams
+        Self::new(seed, hash(&[seed]))
+    }
 
-        z0
+    fn next_u64(&mut self) -> u64 {
+        let high = self.next_u32() as u64;
+        (high << 32) | self.next_u32() as u64
     }
 }
 
-pub(crate) static mut RANDOM: Option<Arc<Mutex<XorShift32>>> = None;
+/// Mix `values` into a well distributed seed, a change of any bit changing about half of them
+pub fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x243F_6A88_85A3_08D3, |hash: u64, value| {
+            let mut x = (hash ^ value).wrapping_add(0x9E37_79B9_7F4A_7C15);
+            x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            x ^ (x >> 31)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn random() -> f32 {
-    unsafe {
-        RANDOM
-            .as_ref()
-            .expect("You have to init the global RANDOM object with init_rand!")
-            .lock()
-            .unwrap()
-            .linear()
+    #[test]
+    fn split_mix_64_known_answers() {
+        let mut s = 1_234_567;
+        let outputs = [
+            6_457_827_717_110_365_317,
+            3_203_168_211_198_807_973,
+            9_817_491_932_198_370_423,
+            4_593_380_528_125_082_431,
+            16_408_922_859_458_223_821,
+        ];
+        for &output in outputs.iter() {
+            assert_eq!(Xoshiro256Plus::split_mix_64(&mut s), output);
+        }
+    }
+
+    #[test]
+    fn xoshiro256_plus_known_answers() {
+        // The state is the first four outputs above, as in the reference seeding
+        let mut rng = Xoshiro256Plus::from_seed(1_234_567);
+        let outputs = [
+            0x995D_C758_E420_77C4,
+            0xB8E7_1A4C_EB44_1E47,
+            0x9C42_FC45_05DF_856A,
+            0x2B8A_74CC_E93C_C5A2,
+            0x0EEB_A288_37B5_014A,
+            0x95E0_9CB4_136B_2DEE,
+        ];
+        for &output in outputs.iter() {
+            assert_eq!(rng.next_u64(), output);
+        }
+    }
+
+    #[test]
+    fn pcg32_known_answers() {
+        // From the demo of the reference implementation, seeded with `pcg32_srandom(42, 54)`
+        let mut rng = Pcg32::new(42, 54);
+        let outputs = [
+            0xA15C_02B7,
+            0x7B47_F409,
+            0xBA1D_3330,
+            0x83D2_F293,
+            0xBFA4_784B,
+            0xCBED_606E,
+        ];
+        for &output in outputs.iter() {
+            assert_eq!(rng.next_u32(), output);
+        }
     }
-}
 
-#[macro_export]
-macro_rules! init_rand {
-    () => {{
-        unsafe {
-            $crate::random::RANDOM =
-                Some(Arc::new(Mutex::new($crate::random::XorShift32::new(None))));
+    #[test]
+    fn linear_from_bits_is_below_one() {
+        assert_eq!(linear_from_bits(0), 0.0);
+        assert!(linear_from_bits(u64::MAX) < 1.0);
+    }
+
+    /// Mean and variance of `count` draws
+    fn moments(count: usize, mut draw: impl FnMut() -> f32) -> (f64, f64) {
+        let (mut sum, mut squared_sum) = (0.0, 0.0);
+        for _ in 0..count {
+            let x = draw() as f64;
+            sum += x;
+            squared_sum += x * x;
+        }
+        let mean = sum / count as f64;
+        (mean, squared_sum / count as f64 - mean * mean)
+    }
+
+    #[test]
+    fn linear_moments() {
+        for generator in [Generator::Xoshiro256Plus, Generator::Pcg32].iter() {
+            let mut rng = generator.seeded(1);
+            let (mean, variance) = moments(1_000_000, || rng.linear());
+            assert!((mean - 0.5).abs() < 2e-3, "{:?}: mean {}", generator, mean);
+            assert!(
+                (variance - 1.0 / 12.0).abs() < 2e-3,
+                "{:?}: variance {}",
+                generator,
+                variance
+            );
         }
-    }};
-    ($seed: expr) => {{
-        unsafe {
-            $crate::random::RANDOM = Some(Arc::new(Mutex::new($crate::random::XorShift32::new(
-                Some($seed),
-            ))));
+    }
+
+    #[test]
+    fn normal_moments() {
+        for generator in [Generator::Xoshiro256Plus, Generator::Pcg32].iter() {
+            let mut rng = generator.seeded(2);
+            let (mean, variance) = moments(1_000_000, || rng.normal());
+            assert!(mean.abs() < 5e-3, "{:?}: mean {}", generator, mean);
+            assert!(
+                (variance - 1.0).abs() < 1e-2,
+                "{:?}: variance {}",
+                generator,
+                variance
+            );
         }
-    }};
+    }
 }
@@ -5,6 +5,9 @@ pub struct Ray {
     origin: Vec3,
     direction: Vec3,
     time: f32,
+    /// Drawn by the sampler, participating media derive from it the distance the ray travels
+    /// before hitting a particle
+    sample: f32,
 }
 
 impl Ray {
@@ -13,9 +16,15 @@ impl Ray {
             origin,
             direction,
             time,
+            sample: 0.0,
         }
     }
 
+    pub const fn with_sample(mut self, sample: f32) -> Self {
+        self.sample = sample;
+        self
+    }
+
     pub const fn origin(&self) -> Vec3 {
         self.origin
     }
@@ -28,6 +37,10 @@ impl Ray {
         self.time
     }
 
+    pub const fn sample(&self) -> f32 {
+        self.sample
+    }
+
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
         self.origin + self.direction * t
     }
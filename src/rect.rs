@@ -0,0 +1,380 @@
+use ::std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{self, FlipNormals, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+/// Thickness given to the bounding box of a rectangle, which has none
+const PADDING: f32 = 1e-4;
+
+/// Intersection of a ray with the rectangle `[a0, a1] x [b0, b1]` of the plane where the
+/// coordinate `axis` equals `k`, `a` and `b` being the two other axes.
+/// Returns the distance and the texture coordinates of the hit point.
+#[allow(clippy::too_many_arguments)]
+fn hit_rect(
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1): (f32, f32),
+    (b0, b1): (f32, f32),
+    k: f32,
+) -> Option<(f32, f32, f32)> {
+    let t = (k - ray.origin()[axis]) / ray.direction()[axis];
+    // Also rejects the NaN of rays parallel to the rectangle
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+
+    let p = ray.point_at_parameter(t);
+    if p[a] < a0 || p[a] > a1 || p[b] < b0 || p[b] > b1 {
+        return None;
+    }
+
+    Some((t, (p[a] - a0) / (a1 - a0), (p[b] - b0) / (b1 - b0)))
+}
+
+/// Uniform point of the rectangle, with the same arguments as `hit_rect`
+fn sample_rect(
+    sampler: &mut dyn Sampler,
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1): (f32, f32),
+    (b0, b1): (f32, f32),
+    k: f32,
+) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    let mut p = Vec3::default();
+    p[a] = a0 + u * (a1 - a0);
+    p[b] = b0 + v * (b1 - b0);
+    p[axis] = k;
+    p
+}
+
+/// Density of `sample_rect` giving a point in `direction` from `origin`
+fn rect_pdf(rect: &dyn Hittable, origin: &Vec3, direction: &Vec3, area: f32) -> f32 {
+    match rect.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f32::MAX) {
+        Some(rec) => hittable::area_to_solid_angle_pdf(direction, rec.t, &rec.normal, area),
+        None => 0.0,
+    }
+}
+
+fn bounding_box(min: Vec3, max: Vec3) -> AABB {
+    let padding = Vec3::new(PADDING, PADDING, PADDING);
+    AABB::new(min - padding, max + padding)
+}
+
+/// Rectangle in the plane `z = k`, facing +z
+#[derive(Debug)]
+pub struct XYRect {
+    x0: f32,
+    x1: f32,
+    y0: f32,
+    y1: f32,
+    k: f32,
+    material: Arc<dyn Material>,
+}
+
+impl XYRect {
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Self {
+            x0,
+            x1,
+            y0,
+            y1,
+            k,
+            material,
+        }
+    }
+}
+
+impl Hittable for XYRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let (t, u, v) = hit_rect(
+            ray,
+            t_min,
+            t_max,
+            (0, 1, 2),
+            (self.x0, self.x1),
+            (self.y0, self.y1),
+            self.k,
+        )?;
+        Some(HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            u,
+            v,
+            material: self.material.clone(),
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(
+            Vec3::new(self.x0, self.y0, self.k),
+            Vec3::new(self.x1, self.y1, self.k),
+        ))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(sample_rect(
+            sampler,
+            (0, 1, 2),
+            (self.x0, self.x1),
+            (self.y0, self.y1),
+            self.k,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, _time: f32) -> f32 {
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        rect_pdf(self, origin, direction, area)
+    }
+}
+
+/// Rectangle in the plane `y = k`, facing +y
+#[derive(Debug)]
+pub struct XZRect {
+    x0: f32,
+    x1: f32,
+    z0: f32,
+    z1: f32,
+    k: f32,
+    material: Arc<dyn Material>,
+}
+
+impl XZRect {
+    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Self {
+            x0,
+            x1,
+            z0,
+            z1,
+            k,
+            material,
+        }
+    }
+}
+
+impl Hittable for XZRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let (t, u, v) = hit_rect(
+            ray,
+            t_min,
+            t_max,
+            (0, 2, 1),
+            (self.x0, self.x1),
+            (self.z0, self.z1),
+            self.k,
+        )?;
+        Some(HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            u,
+            v,
+            material: self.material.clone(),
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(
+            Vec3::new(self.x0, self.k, self.z0),
+            Vec3::new(self.x1, self.k, self.z1),
+        ))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(sample_rect(
+            sampler,
+            (0, 2, 1),
+            (self.x0, self.x1),
+            (self.z0, self.z1),
+            self.k,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, _time: f32) -> f32 {
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        rect_pdf(self, origin, direction, area)
+    }
+}
+
+/// Rectangle in the plane `x = k`, facing +x
+#[derive(Debug)]
+pub struct YZRect {
+    y0: f32,
+    y1: f32,
+    z0: f32,
+    z1: f32,
+    k: f32,
+    material: Arc<dyn Material>,
+}
+
+impl YZRect {
+    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Self {
+            y0,
+            y1,
+            z0,
+            z1,
+            k,
+            material,
+        }
+    }
+}
+
+impl Hittable for YZRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let (t, u, v) = hit_rect(
+            ray,
+            t_min,
+            t_max,
+            (1, 2, 0),
+            (self.y0, self.y1),
+            (self.z0, self.z1),
+            self.k,
+        )?;
+        Some(HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            u,
+            v,
+            material: self.material.clone(),
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(
+            Vec3::new(self.k, self.y0, self.z0),
+            Vec3::new(self.k, self.y1, self.z1),
+        ))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(sample_rect(
+            sampler,
+            (1, 2, 0),
+            (self.y0, self.y1),
+            (self.z0, self.z1),
+            self.k,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, _time: f32) -> f32 {
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        rect_pdf(self, origin, direction, area)
+    }
+}
+
+/// Axis-aligned box made of six rectangles, all facing outwards
+#[derive(Debug)]
+pub struct Box {
+    min: Vec3,
+    max: Vec3,
+    faces: [Arc<dyn Hittable>; 6],
+}
+
+impl Box {
+    pub fn new(min: Vec3, max: Vec3, material: Arc<dyn Material>) -> Self {
+        let (x0, y0, z0) = (min.x(), min.y(), min.z());
+        let (x1, y1, z1) = (max.x(), max.y(), max.z());
+        let faces: [Arc<dyn Hittable>; 6] = [
+            Arc::new(XYRect::new(x0, x1, y0, y1, z1, material.clone())),
+            Arc::new(FlipNormals::new(Arc::new(XYRect::new(
+                x0,
+                x1,
+                y0,
+                y1,
+                z0,
+                material.clone(),
+            )))),
+            Arc::new(XZRect::new(x0, x1, z0, z1, y1, material.clone())),
+            Arc::new(FlipNormals::new(Arc::new(XZRect::new(
+                x0,
+                x1,
+                z0,
+                z1,
+                y0,
+                material.clone(),
+            )))),
+            Arc::new(YZRect::new(y0, y1, z0, z1, x1, material.clone())),
+            Arc::new(FlipNormals::new(Arc::new(YZRect::new(
+                y0, y1, z0, z1, x0, material,
+            )))),
+        ];
+        Self { min, max, faces }
+    }
+
+    /// Areas of the faces, in the order of `faces`
+    fn face_areas(&self) -> [f32; 6] {
+        let d = self.max - self.min;
+        let (xy, xz, yz) = (d.x() * d.y(), d.x() * d.z(), d.y() * d.z());
+        [xy, xy, xz, xz, yz, yz]
+    }
+}
+
+impl Hittable for Box {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut hit_record = None;
+        let mut closest_so_far = t_max;
+        for face in self.faces.iter() {
+            if let Some(rec) = face.hit(ray, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                hit_record = Some(rec);
+            }
+        }
+        hit_record
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(self.min, self.max))
+    }
+
+    /// The faces all have the same material
+    fn is_light(&self) -> bool {
+        self.faces[0].is_light()
+    }
+
+    /// On one of the faces, picked in proportion to its area
+    fn sample_point(&self, origin: &Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let areas = self.face_areas();
+        let mut remaining = sampler.get_1d() * areas.iter().sum::<f32>();
+        // The last face if rounding leaves some of the area
+        let mut index = 5;
+        for (i, area) in areas.iter().enumerate() {
+            if remaining < *area {
+                index = i;
+                break;
+            }
+            remaining -= area;
+        }
+        self.faces[index].sample_point(origin, time, sampler)
+    }
+
+    /// Mixture of the densities of the faces, weighted by their areas
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        let areas = self.face_areas();
+        let total: f32 = areas.iter().sum();
+        self.faces
+            .iter()
+            .zip(areas.iter())
+            .map(|(face, area)| face.pdf_value(origin, direction, time) * (area / total))
+            .sum()
+    }
+}
@@ -0,0 +1,477 @@
+use ::std::str::FromStr;
+
+use crate::random::{self, Generator, Pcg32, Rng, Xoshiro256Plus};
+
+/// Provides the random numbers of the samples of each pixel, dimension after dimension.
+/// The dimensions of a sample are always requested in the same order (pixel position, lens,
+/// time, then each bounce), so samplers can spread them better than independent numbers.
+pub trait Sampler {
+    /// Start the sample `index` of `pixel`
+    fn start_sample(&mut self, pixel: u64, index: u64);
+
+    /// Next dimension, in `[0, 1)`
+    fn get_1d(&mut self) -> f32;
+
+    /// Next two dimensions, distributed together, in `[0, 1)²`
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+/// The samplers which can be chosen for a render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Sampler of a render with `samples` samples per pixel
+    pub fn build(self, seed: u64, samples: usize, generator: Generator) -> Box<dyn Sampler> {
+        match (self, generator) {
+            (SamplerKind::Independent, Generator::Xoshiro256Plus) => {
+                Box::new(IndependentSampler::<Xoshiro256Plus>::new(seed))
+            }
+            (SamplerKind::Independent, Generator::Pcg32) => {
+                Box::new(IndependentSampler::<Pcg32>::new(seed))
+            }
+            (SamplerKind::Stratified, _) => Box::new(StratifiedSampler::new(seed, samples)),
+            (SamplerKind::Halton, _) => Box::new(HaltonSampler::new(seed)),
+            (SamplerKind::Sobol, _) => Box::new(SobolSampler::new(seed, samples)),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Position of the current sample, shared by the samplers
+#[derive(Debug, Default)]
+struct Cursor {
+    pixel: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl Cursor {
+    /// Return the current dimension and skip `count` of them
+    fn advance(&mut self, count: u64) -> u64 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+
+    /// Hash of the current dimension of this pixel, the same for all its samples
+    fn hash(&self, seed: u64, dimension: u64) -> u64 {
+        random::hash(&[seed, self.pixel, dimension])
+    }
+
+    /// Uniform number for an `axis` of a dimension of this sample, unrelated to the others
+    fn uniform(&self, seed: u64, dimension: u64, axis: u64) -> f32 {
+        random::linear_from_bits(random::hash(&[
+            seed, self.pixel, self.index, dimension, axis,
+        ]))
+    }
+}
+
+/// Plain Monte Carlo, every number comes from the random generator
+#[derive(Debug)]
+pub struct IndependentSampler<R> {
+    seed: u64,
+    rng: Option<R>,
+}
+
+impl<R: Rng> IndependentSampler<R> {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: None }
+    }
+
+    fn rng(&mut self) -> &mut R {
+        self.rng
+            .as_mut()
+            .expect("start_sample must be called before sampling")
+    }
+}
+
+impl<R: Rng> Sampler for IndependentSampler<R> {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.rng = Some(random::for_sample(self.seed, pixel, index));
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng().linear()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let rng = self.rng();
+        (rng.linear(), rng.linear())
+    }
+}
+
+/// Jittered grid: each dimension is split in as many strata as there are samples per pixel,
+/// and each sample of a pixel falls in a different stratum. The 2D dimensions are correlated
+/// multi-jittered (Kensler, 2013): each sample falls in a different cell of a square grid,
+/// and their projections on each axis are stratified too.
+#[derive(Debug)]
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    /// Cells along x and y of the 2D dimensions, at least as many as `samples`, some cells
+    /// being left empty when it is not a square
+    cells: (u32, u32),
+    cursor: Cursor,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples: usize) -> Self {
+        let samples = samples as u32;
+        let x = (samples as f32).sqrt().ceil().max(1.0) as u32;
+        Self {
+            seed,
+            samples,
+            cells: (x, samples.div_ceil(x).max(1)),
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Stratum of the current sample for `dimension`, shuffled so dimensions are unrelated
+    fn stratum(&self, dimension: u64) -> u32 {
+        let hash = self.cursor.hash(self.seed, dimension) as u32;
+        permute(self.cursor.index as u32 % self.samples, self.samples, hash)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.cursor = Cursor {
+            pixel,
+            index,
+            dimension: 0,
+        };
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.cursor.advance(1);
+        let jitter = self.cursor.uniform(self.seed, dimension, 0);
+        ((self.stratum(dimension) as f32 + jitter) / self.samples as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let dimension = self.cursor.advance(1);
+        let cell = self.stratum(dimension);
+        let (nx, ny) = self.cells;
+        let (cell_x, cell_y) = (cell % nx, cell / nx);
+        // Within its cell, the sample is moved to a sub-stratum of the other axis, the same
+        // for a whole column or row of cells so that the projections stay stratified
+        let seeds = random::hash(&[self.cursor.hash(self.seed, dimension)]);
+        let sub_x = permute(cell_y, ny, seeds as u32);
+        let sub_y = permute(cell_x, nx, (seeds >> 32) as u32);
+        let jitter_x = self.cursor.uniform(self.seed, dimension, 0);
+        let jitter_y = self.cursor.uniform(self.seed, dimension, 1);
+        let x = (cell_x as f32 + (sub_x as f32 + jitter_x) / ny as f32) / nx as f32;
+        let y = (cell_y as f32 + (sub_y as f32 + jitter_y) / nx as f32) / ny as f32;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Halton sequence, one prime base per dimension, Owen-scrambled differently in each pixel.
+/// Dimensions beyond the table of primes are independent random numbers.
+#[derive(Debug)]
+pub struct HaltonSampler {
+    seed: u64,
+    cursor: Cursor,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            cursor: Cursor::default(),
+        }
+    }
+
+    fn sample(&self, dimension: u64) -> f32 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => scrambled_radical_inverse(
+                self.cursor.index,
+                base,
+                self.cursor.hash(self.seed, dimension),
+            ),
+            None => self.cursor.uniform(self.seed, dimension, 0),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.cursor = Cursor {
+            pixel,
+            index,
+            dimension: 0,
+        };
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.cursor.advance(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let dimension = self.cursor.advance(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+}
+
+/// `index` written in `base` and mirrored around the radix point, each digit being permuted
+/// according to `hash` and the digits before it. The leading zeros are permuted too, or the
+/// large bases would leave their first samples all close to 0.
+fn scrambled_radical_inverse(mut index: u64, base: u32, hash: u64) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let (mut reversed, mut inv_base_n) = (0u64, 1.0);
+    // Until the digits are too small to change an f32
+    while inv_base_n * base as f64 > f32::EPSILON as f64 {
+        let digit = (index % base as u64) as u32;
+        index /= base as u64;
+        let digit = permute(digit, base, random::hash(&[hash, reversed]) as u32);
+        reversed = reversed * base as u64 + digit as u64;
+        inv_base_n *= inv_base;
+    }
+    ((reversed as f64 * inv_base_n) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// The first two dimensions of the Sobol sequence, Owen-scrambled. Each dimension of a sample
+/// gets its own scrambling and its own shuffling of the sample indices, so they are unrelated
+/// to each other while keeping the stratification of the sequence. The samples of a pixel
+/// are the first points of the sequence, which are only evenly stratified when there is a
+/// power of two of them.
+#[derive(Debug)]
+pub struct SobolSampler {
+    seed: u64,
+    samples: u32,
+    cursor: Cursor,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64, samples: usize) -> Self {
+        Self {
+            seed,
+            samples: samples as u32,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Shuffled index and scrambling seeds of `dimension`
+    fn scramble(&mut self) -> (u32, u64) {
+        let dimension = self.cursor.advance(1);
+        let hash = self.cursor.hash(self.seed, dimension);
+        let index = permute(
+            self.cursor.index as u32 % self.samples,
+            self.samples,
+            hash as u32,
+        );
+        (index, hash)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.cursor = Cursor {
+            pixel,
+            index,
+            dimension: 0,
+        };
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (index, hash) = self.scramble();
+        to_unit(owen_scramble(index.reverse_bits(), (hash >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (index, hash) = self.scramble();
+        let seeds = random::hash(&[hash]);
+        (
+            to_unit(owen_scramble(index.reverse_bits(), seeds as u32)),
+            to_unit(owen_scramble(
+                sobol_second_dimension(index),
+                (seeds >> 32) as u32,
+            )),
+        )
+    }
+}
+
+/// Second dimension of the Sobol sequence, whose primitive polynomial is `x + 1`
+fn sobol_second_dimension(index: u32) -> u32 {
+    let (mut result, mut direction) = (0, 1 << 31);
+    let mut index = index;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    result
+}
+
+/// Hash-based Owen scrambling of the bits of `v`, from the most significant one
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3D20_ADEA);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6C56);
+    v ^= v.wrapping_mul(0x53A2_2864);
+    v.reverse_bits()
+}
+
+/// Element `i` of a random permutation of `0..length` chosen by `seed` (Kensler, 2013)
+fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    if length <= 1 {
+        return 0;
+    }
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xE170_893D);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_EB3F);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_FA69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74DC_B303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9E50_1CC3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xC860_A3DF);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % length
+}
+
+/// Largest `f32` below 1
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// `[0, 1)` from the 24 highest bits, which all fit in the mantissa
+fn to_unit(bits: u32) -> f32 {
+    random::linear_from_bits((bits as u64) << 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::mem;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    /// The first 1D and 2D dimensions of the samples of a pixel
+    fn samples(kind: SamplerKind, count: usize) -> (Vec<f32>, Vec<(f32, f32)>) {
+        let mut sampler = kind.build(3, count, Generator::Pcg32);
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(42, index as u64);
+                (sampler.get_1d(), sampler.get_2d())
+            })
+            .unzip()
+    }
+
+    /// Whether each value falls in a different one of `strata` intervals
+    fn one_per_stratum(mut values: impl Iterator<Item = f32>, strata: usize) -> bool {
+        let mut found = vec![false; strata];
+        values.all(|value| !mem::replace(&mut found[(value * strata as f32) as usize], true))
+    }
+
+    #[test]
+    fn unit_interval() {
+        for &kind in KINDS.iter() {
+            for &count in [1, 7, 16, 100].iter() {
+                let mut sampler = kind.build(0, count, Generator::Xoshiro256Plus);
+                for index in 0..count {
+                    sampler.start_sample(index as u64 * 31, index as u64);
+                    for _ in 0..40 {
+                        let value = sampler.get_1d();
+                        assert!((0.0..1.0).contains(&value), "{:?} {}", kind, value);
+                        let (u, v) = sampler.get_2d();
+                        assert!((0.0..1.0).contains(&u), "{:?} {}", kind, u);
+                        assert!((0.0..1.0).contains(&v), "{:?} {}", kind, v);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified() {
+        // Square, prime, and neither
+        for &count in [16, 7, 12].iter() {
+            let (values, points) = samples(SamplerKind::Stratified, count);
+            assert!(one_per_stratum(values.into_iter(), count));
+
+            let (nx, ny) = StratifiedSampler::new(0, count).cells;
+            assert!(nx * ny >= count as u32 && nx.abs_diff(ny) <= 1);
+            let cells = points.iter().map(|&(u, v)| {
+                let cell = (u * nx as f32).floor() + (v * ny as f32).floor() * nx as f32;
+                (cell + 0.5) / (nx * ny) as f32
+            });
+            assert!(one_per_stratum(cells, (nx * ny) as usize));
+            let strata = (nx * ny) as usize;
+            assert!(one_per_stratum(points.iter().map(|&(u, _)| u), strata));
+            assert!(one_per_stratum(points.iter().map(|&(_, v)| v), strata));
+        }
+    }
+
+    #[test]
+    fn base_two_radical_inverse() {
+        // Scrambling permutes the strata of the radical inverse of 0..16 without leaving them,
+        // and Halton takes the samples in order while Sobol shuffles them
+        let (values, _) = samples(SamplerKind::Halton, 16);
+        for &strata in [2, 4, 8, 16].iter() {
+            assert!(one_per_stratum(values[..strata].iter().copied(), strata));
+        }
+        let (values, _) = samples(SamplerKind::Sobol, 16);
+        assert!(one_per_stratum(values.into_iter(), 16));
+        // From the direction numbers 1/2, 3/4 and 5/8, without Gray code ordering
+        assert_eq!(
+            (0..8).map(sobol_second_dimension).collect::<Vec<_>>(),
+            [0, 4, 6, 2, 5, 1, 3, 7]
+                .iter()
+                .map(|&eighths| eighths << 29)
+                .collect::<Vec<_>>()
+        );
+    }
+}
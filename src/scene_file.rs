@@ -0,0 +1,1386 @@
+//! Text format describing a scene, for example:
+//!
+//! ```text
+//! # Comments start with a sharp
+//! camera { look_from = (13, 2, 3), look_at = (0, 0, 0), vfov = 20 }
+//!
+//! texture green = (0.2, 0.3, 0.1)
+//! texture ground = checker { odd = green, even = (0.9, 0.9, 0.9) }
+//!
+//! material floor = lambertian { albedo = ground }
+//! material glass = dielectric { ref_idx = 1.5 }
+//!
+//! sphere { center = (0, -1000, 0), radius = 1000, material = floor }
+//! sphere { center = (0, 1, 0), radius = 1, material = metal { albedo = (0.7, 0.6, 0.5), fuzz = 0 } }
+//! ```
+//!
+//! A file is a list of statements, each one being either the `camera`, the `background`
+//! (`none`, a color, or `gradient { bottom = COLOR, top = COLOR }`), a named definition
+//! (`texture NAME = VALUE` or `material NAME = VALUE`), or an object added to the scene.
+//! Fields of a block can be separated by commas or newlines, and a value can be a number,
+//! a vector `(x, y, z)`, texture coordinates `(u, v)`, the name of a definition, an inline
+//! block, or a list `[a, b, ...]`.
+//!
+//! Rectangles are aligned with two axes, boxes with all three, and `flip` turns an object
+//! inside out so that it faces the other way:
+//!
+//! ```text
+//! xz_rect { x0 = 213, x1 = 343, z0 = 227, z1 = 332, y = 554, material = light }
+//! flip { object = yz_rect { y0 = 0, y1 = 555, z0 = 0, z1 = 555, x = 555, material = green } }
+//! box { min = (130, 0, 65), max = (295, 165, 230), material = white }
+//! ```
+//!
+//! Any object can be placed with `transform`, which scales it (by a number or a vector),
+//! rotates it around the x, then y, then z axis (in degrees), then translates it:
+//!
+//! ```text
+//! transform {
+//!     object = box { min = (0, 0, 0), max = (165, 330, 165), material = white }
+//!     scale = 1, rotate = (0, 15, 0), translate = (265, 0, 295)
+//! }
+//! ```
+//!
+//! Smoke and fog are volumes of constant density filling the boundary of another object,
+//! their particles scattering light in every direction like the `isotropic` material:
+//!
+//! ```text
+//! constant_medium { boundary = sphere { center = (0, 1, 0), radius = 1, material = glass }, density = 0.2, albedo = (1, 1, 1) }
+//! ```
+//!
+//! Lights without a surface are never seen by the camera nor in reflections, they only light
+//! the objects: points shining in every direction, spots shining in a cone fading out over
+//! `falloff` degrees inside its edge at `angle` degrees from its axis, and directional lights
+//! like the sun, whose `angular_diameter` in degrees softens the shadows:
+//!
+//! ```text
+//! point_light { position = (0, 5, 0), intensity = (20, 20, 20) }
+//! spot_light { position = (0, 5, 0), direction = (0, -1, 0), intensity = (50, 50, 50), angle = 30, falloff = 5 }
+//! directional_light { direction = (-1, -2, -1), irradiance = (3, 3, 3), angular_diameter = 0.5 }
+//! ```
+//!
+//! Image textures are read from a file relative to the scene file, filtered and wrapped:
+//!
+//! ```text
+//! texture earth = image { path = "earthmap.jpg", filter = bilinear, wrap = repeat }
+//! ```
+//!
+//! Models in the Wavefront OBJ format are imported with the materials of their MTL file:
+//!
+//! ```text
+//! obj { path = "model.obj" }  # relative to the scene file
+//! ```
+//!
+//! Meshes list their vertices and the indices of the vertices of each triangle:
+//!
+//! ```text
+//! mesh {
+//!     positions = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)]
+//!     triangles = [(0, 1, 2), (0, 2, 3)]
+//!     uvs = [(0, 0), (1, 0), (1, 1), (0, 1)]  # optional, as `normals`
+//!     material = glass
+//! }
+//! ```
+
+use ::std::collections::HashMap;
+use ::std::fmt;
+use ::std::fs;
+use ::std::io;
+use ::std::path::{Path, PathBuf};
+use ::std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::background::Background;
+use crate::camera::CameraSettings;
+use crate::hittable::{FlipNormals, Hittable, Scene};
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::matrix::Matrix4;
+use crate::medium::ConstantMedium;
+use crate::mesh::TriangleMesh;
+use crate::moving_sphere::MovingSphere;
+use crate::obj::{self, ObjError};
+use crate::rect::{self, XYRect, XZRect, YZRect};
+use crate::sphere::Sphere;
+use crate::texture::{
+    CheckerTexture, ConstantTexture, Filter, ImageTexture, NoiseTexture, Texture, Wrap,
+};
+use crate::transform::Transformed;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Obj(ObjError),
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl SceneError {
+    fn new<S: Into<String>>(position: Position, message: S) -> Self {
+        SceneError::Syntax {
+            line: position.line,
+            column: position.column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e),
+            SceneError::Obj(e) => write!(f, "{}", e),
+            SceneError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}: {}", line, column, message),
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+type Result<T> = ::std::result::Result<T, SceneError>;
+
+/// A scene read from a file, along with the point of view it should be rendered from
+#[derive(Debug)]
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: CameraSettings,
+}
+
+impl SceneFile {
+    /// Wavefront OBJ files are loaded as a scene with the camera looking at the whole model
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|extension| extension == "obj") {
+            return Self::from_obj(path);
+        }
+
+        let source = fs::read_to_string(path)?;
+        Self::parse(&source, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Paths in the scene are relative to `directory`
+    pub fn parse(source: &str, directory: &Path) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let statements = Parser::new(tokens).parse_statements()?;
+        Loader::new(directory).load(&statements)
+    }
+
+    fn from_obj(path: &Path) -> Result<Self> {
+        let meshes = obj::load(path, default_material()).map_err(SceneError::Obj)?;
+
+        let mut camera = CameraSettings::default();
+        let aabb = meshes
+            .iter()
+            .filter_map(|mesh| mesh.bounding_box(0.0, 0.0))
+            .reduce(AABB::surrounding_box);
+        if let Some(aabb) = aabb {
+            let center = aabb.centroid();
+            let radius = (aabb.max() - aabb.min()).length() / 2.0;
+            let distance = radius / (camera.vfov.to_radians() / 2.0).sin();
+            let direction = (camera.look_from - camera.look_at).unit_vector();
+            camera.look_from = center + direction * distance;
+            camera.look_at = center;
+            camera.focus_dist = distance;
+        }
+
+        let hittables = meshes
+            .into_iter()
+            .map(|mesh| Arc::new(mesh) as Arc<dyn Hittable>)
+            .collect();
+        Ok(Self {
+            scene: Scene::new(hittables),
+            camera,
+        })
+    }
+}
+
+/// Material of the faces of an OBJ file without material
+fn default_material() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(Arc::new(ConstantTexture::new(Vec3::new(
+        0.8, 0.8, 0.8,
+    )))))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f32),
+    Str(String),
+    Symbol(char),
+    Newline,
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "`{}`", ident),
+            Token::Number(number) => write!(f, "`{}`", number),
+            Token::Str(string) => write!(f, "{:?}", string),
+            Token::Symbol(symbol) => write!(f, "`{}`", symbol),
+            Token::Newline => write!(f, "end of line"),
+            Token::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let position = Position {
+                line: line_index + 1,
+                column: i + 1,
+            };
+            let c = chars[i];
+
+            if c.is_whitespace() {
+                i += 1;
+            } else if c == '#' {
+                break;
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let ident = chars[start..i].iter().collect();
+                tokens.push((Token::Ident(ident), position));
+            } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+                let start = i;
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit()
+                        || chars[i] == '.'
+                        || chars[i] == 'e'
+                        || chars[i] == 'E'
+                        || ((chars[i] == '-' || chars[i] == '+')
+                            && (chars[i - 1] == 'e' || chars[i - 1] == 'E')))
+                {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let number = number.parse().map_err(|_| {
+                    SceneError::new(position, format!("invalid number `{}`", number))
+                })?;
+                tokens.push((Token::Number(number), position));
+            } else if c == '"' {
+                let mut string = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            string.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            string.push(c);
+                            i += 1;
+                        }
+                        None => return Err(SceneError::new(position, "unterminated string")),
+                    }
+                }
+                i += 1;
+                tokens.push((Token::Str(string), position));
+            } else if "{}()[]=,".contains(c) {
+                tokens.push((Token::Symbol(c), position));
+                i += 1;
+            } else {
+                return Err(SceneError::new(
+                    position,
+                    format!("unexpected character `{}`", c),
+                ));
+            }
+        }
+
+        tokens.push((
+            Token::Newline,
+            Position {
+                line: line_index + 1,
+                column: chars.len() + 1,
+            },
+        ));
+    }
+
+    let position = tokens
+        .last()
+        .map(|(_, position)| *position)
+        .unwrap_or(Position { line: 1, column: 1 });
+    tokens.push((Token::Eof, position));
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(f32),
+    Vector(Vec3),
+    /// Texture coordinates `(u, v)`
+    Pair(f32, f32),
+    Str(String),
+    Ident(String),
+    Block(Block),
+    List(Vec<(Value, Position)>),
+}
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Vector(_) => "a vector",
+            Value::Pair(..) => "a pair",
+            Value::Str(_) => "a string",
+            Value::Ident(_) => "a name",
+            Value::Block(_) => "a block",
+            Value::List(_) => "a list",
+        }
+    }
+}
+
+/// `kind { name = value, ... }`
+#[derive(Debug)]
+struct Block {
+    kind: String,
+    position: Position,
+    fields: Vec<(String, Position, Value, Position)>,
+}
+
+#[derive(Debug)]
+enum Statement {
+    /// `category name = value`
+    Definition {
+        category: String,
+        name: String,
+        position: Position,
+        value: Value,
+        value_position: Position,
+    },
+    /// `name = value`
+    Setting {
+        name: String,
+        position: Position,
+        value: Value,
+        value_position: Position,
+    },
+    Block(Block),
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize,
+}
+
+impl Parser {
+    fn new(tokens: Vec<(Token, Position)>) -> Self {
+        Self { tokens, index: 0 }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.index].1
+    }
+
+    fn next(&mut self) -> (Token, Position) {
+        let token = self.tokens[self.index].clone();
+        if self.index + 1 < self.tokens.len() {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn skip_newlines(&mut self) {
+        while *self.peek() == Token::Newline {
+            self.next();
+        }
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T> {
+        Err(SceneError::new(
+            self.position(),
+            format!("expected {}, found {}", expected, self.peek()),
+        ))
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<()> {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next();
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", symbol))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<(String, Position)> {
+        match self.peek() {
+            Token::Ident(_) => match self.next() {
+                (Token::Ident(ident), position) => Ok((ident, position)),
+                _ => unreachable!(),
+            },
+            _ => self.unexpected("a name"),
+        }
+    }
+
+    fn parse_statements(&mut self) -> Result<Vec<Statement>> {
+        let mut statements = Vec::new();
+
+        loop {
+            self.skip_newlines();
+            if *self.peek() == Token::Eof {
+                break Ok(statements);
+            }
+
+            let (ident, position) = self.expect_ident()?;
+            let statement = match self.peek() {
+                Token::Ident(_) => {
+                    let (name, _) = self.expect_ident()?;
+                    self.expect_symbol('=')?;
+                    let value_position = self.position();
+                    let value = self.parse_value()?;
+                    Statement::Definition {
+                        category: ident,
+                        name,
+                        position,
+                        value,
+                        value_position,
+                    }
+                }
+                Token::Symbol('=') => {
+                    self.next();
+                    let value_position = self.position();
+                    let value = self.parse_value()?;
+                    Statement::Setting {
+                        name: ident,
+                        position,
+                        value,
+                        value_position,
+                    }
+                }
+                Token::Symbol('{') => Statement::Block(self.parse_block(ident, position)?),
+                _ => return self.unexpected("`{`, `=` or a name"),
+            };
+            statements.push(statement);
+
+            match self.peek() {
+                Token::Newline | Token::Eof => {}
+                _ => return self.unexpected("end of line"),
+            }
+        }
+    }
+
+    fn parse_block(&mut self, kind: String, position: Position) -> Result<Block> {
+        self.expect_symbol('{')?;
+        let mut fields: Vec<(String, Position, Value, Position)> = Vec::new();
+
+        loop {
+            while *self.peek() == Token::Newline || *self.peek() == Token::Symbol(',') {
+                self.next();
+            }
+            if *self.peek() == Token::Symbol('}') {
+                self.next();
+                break;
+            }
+            if *self.peek() == Token::Eof {
+                return Err(SceneError::new(
+                    position,
+                    format!("unterminated `{}` block", kind),
+                ));
+            }
+
+            let (name, name_position) = self.expect_ident()?;
+            if fields.iter().any(|(field, ..)| *field == name) {
+                return Err(SceneError::new(
+                    name_position,
+                    format!("field `{}` is given twice", name),
+                ));
+            }
+            self.expect_symbol('=')?;
+            let value_position = self.position();
+            let value = self.parse_value()?;
+            fields.push((name, name_position, value, value_position));
+
+            match self.peek() {
+                Token::Newline | Token::Symbol(',') | Token::Symbol('}') => {}
+                _ => return self.unexpected("`,` or `}`"),
+            }
+        }
+
+        Ok(Block {
+            kind,
+            position,
+            fields,
+        })
+    }
+
+    fn parse_number(&mut self) -> Result<f32> {
+        match *self.peek() {
+            Token::Number(number) => {
+                self.next();
+                Ok(number)
+            }
+            _ => self.unexpected("a number"),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        if !self.starts_value() {
+            return self.unexpected("a value");
+        }
+
+        let (token, position) = self.next();
+        match token {
+            Token::Number(number) => Ok(Value::Number(number)),
+            Token::Str(string) => Ok(Value::Str(string)),
+            Token::Ident(ident) => {
+                if *self.peek() == Token::Symbol('{') {
+                    Ok(Value::Block(self.parse_block(ident, position)?))
+                } else {
+                    Ok(Value::Ident(ident))
+                }
+            }
+            Token::Symbol('(') => {
+                let x = self.parse_number()?;
+                self.expect_symbol(',')?;
+                let y = self.parse_number()?;
+                if *self.peek() == Token::Symbol(')') {
+                    self.next();
+                    return Ok(Value::Pair(x, y));
+                }
+                self.expect_symbol(',')?;
+                let z = self.parse_number()?;
+                self.expect_symbol(')')?;
+                Ok(Value::Vector(Vec3::new(x, y, z)))
+            }
+            Token::Symbol('[') => {
+                let mut values = Vec::new();
+                loop {
+                    while *self.peek() == Token::Newline || *self.peek() == Token::Symbol(',') {
+                        self.next();
+                    }
+                    if *self.peek() == Token::Symbol(']') {
+                        self.next();
+                        break Ok(Value::List(values));
+                    }
+                    let position = self.position();
+                    values.push((self.parse_value()?, position));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn starts_value(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::Number(_)
+                | Token::Str(_)
+                | Token::Ident(_)
+                | Token::Symbol('(')
+                | Token::Symbol('[')
+        )
+    }
+}
+
+/// Gives access to the fields of a block, and reports the ones which were never read
+struct Fields<'a> {
+    block: &'a Block,
+    used: Vec<bool>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(block: &'a Block) -> Self {
+        Self {
+            block,
+            used: vec![false; block.fields.len()],
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Option<(&'a Value, Position)> {
+        let index = self
+            .block
+            .fields
+            .iter()
+            .position(|(field, ..)| field == name)?;
+        self.used[index] = true;
+        let (_, _, value, position) = &self.block.fields[index];
+        Some((value, *position))
+    }
+
+    fn require(&mut self, name: &str) -> Result<(&'a Value, Position)> {
+        self.get(name).ok_or_else(|| {
+            SceneError::new(
+                self.block.position,
+                format!("missing field `{}` in `{}`", name, self.block.kind),
+            )
+        })
+    }
+
+    fn number(&mut self, name: &str) -> Result<f32> {
+        let (value, position) = self.require(name)?;
+        as_number(value, position)
+    }
+
+    fn number_or(&mut self, name: &str, default: f32) -> Result<f32> {
+        match self.get(name) {
+            Some((value, position)) => as_number(value, position),
+            None => Ok(default),
+        }
+    }
+
+    fn vector(&mut self, name: &str) -> Result<Vec3> {
+        let (value, position) = self.require(name)?;
+        as_vector(value, position)
+    }
+
+    fn vector_or(&mut self, name: &str, default: Vec3) -> Result<Vec3> {
+        match self.get(name) {
+            Some((value, position)) => as_vector(value, position),
+            None => Ok(default),
+        }
+    }
+
+    /// The identifier with its position, `default` if the field is missing
+    fn ident_or(&mut self, name: &str, default: &'a str) -> Result<(&'a str, Position)> {
+        match self.get(name) {
+            Some((Value::Ident(ident), position)) => Ok((ident, position)),
+            Some((value, position)) => mismatch("a name", value, position),
+            None => Ok((default, self.block.position)),
+        }
+    }
+
+    fn string(&mut self, name: &str) -> Result<&'a str> {
+        let (value, position) = self.require(name)?;
+        match value {
+            Value::Str(string) => Ok(string),
+            _ => mismatch("a string", value, position),
+        }
+    }
+
+    fn vectors(&mut self, name: &str) -> Result<Vec<Vec3>> {
+        let (value, position) = self.require(name)?;
+        as_vectors(value, position)
+    }
+
+    fn vectors_or_empty(&mut self, name: &str) -> Result<Vec<Vec3>> {
+        match self.get(name) {
+            Some((value, position)) => as_vectors(value, position),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn pairs_or_empty(&mut self, name: &str) -> Result<Vec<(f32, f32)>> {
+        let (value, position) = match self.get(name) {
+            Some(field) => field,
+            None => return Ok(Vec::new()),
+        };
+        match value {
+            Value::List(values) => values
+                .iter()
+                .map(|(value, position)| match value {
+                    Value::Pair(u, v) => Ok((*u, *v)),
+                    _ => mismatch("a pair", value, *position),
+                })
+                .collect(),
+            _ => mismatch("a list of pairs", value, position),
+        }
+    }
+
+    /// Fails on the first field which was not read
+    fn finish(self) -> Result<()> {
+        match self.used.iter().position(|used| !used) {
+            Some(index) => {
+                let (name, position, ..) = &self.block.fields[index];
+                Err(SceneError::new(
+                    *position,
+                    format!("unknown field `{}` in `{}`", name, self.block.kind),
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn mismatch<T>(expected: &str, value: &Value, position: Position) -> Result<T> {
+    Err(SceneError::new(
+        position,
+        format!("expected {}, found {}", expected, value.describe()),
+    ))
+}
+
+fn as_number(value: &Value, position: Position) -> Result<f32> {
+    match value {
+        Value::Number(number) => Ok(*number),
+        _ => mismatch("a number", value, position),
+    }
+}
+
+fn as_vector(value: &Value, position: Position) -> Result<Vec3> {
+    match value {
+        Value::Vector(vector) => Ok(*vector),
+        _ => mismatch("a vector", value, position),
+    }
+}
+
+fn as_vectors(value: &Value, position: Position) -> Result<Vec<Vec3>> {
+    match value {
+        Value::List(values) => values
+            .iter()
+            .map(|(value, position)| as_vector(value, *position))
+            .collect(),
+        _ => mismatch("a list of vectors", value, position),
+    }
+}
+
+/// Indices are written as vectors of integers
+fn as_indices(value: &Value, position: Position, len: usize) -> Result<Vec<[usize; 3]>> {
+    let values = match value {
+        Value::List(values) => values,
+        _ => return mismatch("a list of vectors", value, position),
+    };
+    values
+        .iter()
+        .map(|(value, position)| {
+            let vector = as_vector(value, *position)?;
+            let mut indices = [0; 3];
+            for (index, coordinate) in indices.iter_mut().zip(vector.data.iter()) {
+                if coordinate.fract() != 0.0 || *coordinate < 0.0 || *coordinate >= len as f32 {
+                    return Err(SceneError::new(
+                        *position,
+                        format!("`{}` is not the index of a vertex", coordinate),
+                    ));
+                }
+                *index = *coordinate as usize;
+            }
+            Ok(indices)
+        })
+        .collect()
+}
+
+struct Loader {
+    /// Directory the paths are relative to
+    directory: PathBuf,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    camera: Option<CameraSettings>,
+    background: Option<Background>,
+    hittables: Vec<Arc<dyn Hittable>>,
+    lights: Vec<Arc<dyn Light>>,
+}
+
+impl Loader {
+    fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            camera: None,
+            background: None,
+            hittables: Vec::new(),
+            lights: Vec::new(),
+        }
+    }
+
+    fn load(mut self, statements: &[Statement]) -> Result<SceneFile> {
+        for statement in statements {
+            match statement {
+                Statement::Definition {
+                    category,
+                    name,
+                    position,
+                    value,
+                    value_position,
+                } => match category.as_str() {
+                    "texture" => {
+                        let texture = self.texture(value, *value_position)?;
+                        if self.textures.insert(name.clone(), texture).is_some() {
+                            return Err(SceneError::new(
+                                *position,
+                                format!("texture `{}` is defined twice", name),
+                            ));
+                        }
+                    }
+                    "material" => {
+                        let material = self.material(value, *value_position)?;
+                        if self.materials.insert(name.clone(), material).is_some() {
+                            return Err(SceneError::new(
+                                *position,
+                                format!("material `{}` is defined twice", name),
+                            ));
+                        }
+                    }
+                    _ => {
+                        return Err(SceneError::new(
+                            *position,
+                            format!(
+                                "unknown definition `{}`, expected `texture` or `material`",
+                                category
+                            ),
+                        ))
+                    }
+                },
+                Statement::Setting {
+                    name,
+                    position,
+                    value,
+                    value_position,
+                } => match name.as_str() {
+                    "background" => {
+                        if self.background.is_some() {
+                            return Err(SceneError::new(*position, "background is set twice"));
+                        }
+                        self.background = Some(Self::background(value, *value_position)?);
+                    }
+                    _ => {
+                        return Err(SceneError::new(
+                            *position,
+                            format!("unknown setting `{}`", name),
+                        ))
+                    }
+                },
+                Statement::Block(block) if block.kind == "camera" => {
+                    if self.camera.is_some() {
+                        return Err(SceneError::new(block.position, "camera is defined twice"));
+                    }
+                    self.camera = Some(Self::camera(block)?);
+                }
+                Statement::Block(block) if block.kind == "obj" => {
+                    let meshes = self.obj(block)?;
+                    self.hittables.extend(meshes);
+                }
+                Statement::Block(block) if block.kind.ends_with("_light") => {
+                    let light = Self::light(block)?;
+                    self.lights.push(light);
+                }
+                Statement::Block(block) => {
+                    let hittable = self.hittable(block)?;
+                    self.hittables.push(hittable);
+                }
+            }
+        }
+
+        Ok(SceneFile {
+            scene: Scene::new(self.hittables)
+                .with_lights(self.lights)
+                .with_background(self.background.unwrap_or_default()),
+            camera: self.camera.unwrap_or_default(),
+        })
+    }
+
+    fn camera(block: &Block) -> Result<CameraSettings> {
+        let default = CameraSettings::default();
+        let mut fields = Fields::new(block);
+        let camera = CameraSettings {
+            look_from: fields.vector_or("look_from", default.look_from)?,
+            look_at: fields.vector_or("look_at", default.look_at)?,
+            v_up: fields.vector_or("up", default.v_up)?,
+            vfov: fields.number_or("vfov", default.vfov)?,
+            aperture: fields.number_or("aperture", default.aperture)?,
+            focus_dist: fields.number_or("focus_dist", default.focus_dist)?,
+            initial_time: fields.number_or("time0", default.initial_time)?,
+            final_time: fields.number_or("time1", default.final_time)?,
+        };
+        fields.finish()?;
+        Ok(camera)
+    }
+
+    fn light(block: &Block) -> Result<Arc<dyn Light>> {
+        let mut fields = Fields::new(block);
+        let direction = |fields: &mut Fields| {
+            let (value, position) = fields.require("direction")?;
+            match as_vector(value, position)? {
+                direction if direction.squared_length() > 0.0 => Ok(direction),
+                _ => Err(SceneError::new(position, "the direction cannot be zero")),
+            }
+        };
+        let light: Arc<dyn Light> = match block.kind.as_str() {
+            "point_light" => Arc::new(PointLight::new(
+                fields.vector("position")?,
+                fields.vector("intensity")?,
+            )),
+            "spot_light" => {
+                let position = fields.vector("position")?;
+                let direction = direction(&mut fields)?;
+                let intensity = fields.vector("intensity")?;
+                let angle = fields.number_or("angle", 30.0)?;
+                let falloff = fields.number_or("falloff", 5.0)?;
+                if !(angle > 0.0 && angle <= 180.0 && falloff >= 0.0) {
+                    return Err(SceneError::new(
+                        block.position,
+                        "the angle of a spot must be in (0, 180] and its falloff positive",
+                    ));
+                }
+                Arc::new(SpotLight::new(
+                    position, direction, intensity, angle, falloff,
+                ))
+            }
+            "directional_light" => {
+                let direction = direction(&mut fields)?;
+                let irradiance = fields.vector("irradiance")?;
+                let angular_diameter = fields.number_or("angular_diameter", 0.0)?;
+                if !(0.0..=180.0).contains(&angular_diameter) {
+                    return Err(SceneError::new(
+                        block.position,
+                        "the angular diameter must be in [0, 180]",
+                    ));
+                }
+                Arc::new(DirectionalLight::new(
+                    direction,
+                    irradiance,
+                    angular_diameter,
+                ))
+            }
+            kind => {
+                return Err(SceneError::new(
+                    block.position,
+                    format!("unknown light `{}`", kind),
+                ))
+            }
+        };
+        fields.finish()?;
+        Ok(light)
+    }
+
+    /// `none`, a color, or a `gradient` block
+    fn background(value: &Value, position: Position) -> Result<Background> {
+        match value {
+            Value::Ident(name) if name == "none" => Ok(Background::None),
+            Value::Vector(color) => Ok(Background::Solid(*color)),
+            Value::Block(block) if block.kind == "gradient" => {
+                let mut fields = Fields::new(block);
+                let background = Background::Gradient {
+                    bottom: fields.vector_or("bottom", Background::SKY_BOTTOM)?,
+                    top: fields.vector_or("top", Background::SKY_TOP)?,
+                };
+                fields.finish()?;
+                Ok(background)
+            }
+            _ => mismatch("`none`, a color or a `gradient` block", value, position),
+        }
+    }
+
+    /// A texture is either a name, a constant color, or a block
+    fn texture(&self, value: &Value, position: Position) -> Result<Arc<dyn Texture>> {
+        let block = match value {
+            Value::Ident(name) => {
+                return self.textures.get(name).cloned().ok_or_else(|| {
+                    SceneError::new(position, format!("unknown texture `{}`", name))
+                })
+            }
+            Value::Vector(color) => return Ok(Arc::new(ConstantTexture::new(*color))),
+            Value::Block(block) => block,
+            _ => return mismatch("a texture", value, position),
+        };
+
+        let mut fields = Fields::new(block);
+        let texture: Arc<dyn Texture> = match block.kind.as_str() {
+            "constant" => Arc::new(ConstantTexture::new(fields.vector("color")?)),
+            "checker" => {
+                let (odd, odd_position) = fields.require("odd")?;
+                let (even, even_position) = fields.require("even")?;
+                Arc::new(CheckerTexture::new(
+                    self.texture(odd, odd_position)?,
+                    self.texture(even, even_position)?,
+                ))
+            }
+            "noise" => Arc::new(NoiseTexture::new(fields.number_or("scale", 1.0)?)),
+            "image" => {
+                let path = self.directory.join(fields.string("path")?);
+                let filter = match fields.ident_or("filter", "bilinear")? {
+                    ("nearest", _) => Filter::Nearest,
+                    ("bilinear", _) => Filter::Bilinear,
+                    (filter, position) => {
+                        return Err(SceneError::new(
+                            position,
+                            format!(
+                                "unknown filter `{}`, expected `nearest` or `bilinear`",
+                                filter
+                            ),
+                        ))
+                    }
+                };
+                let wrap = match fields.ident_or("wrap", "repeat")? {
+                    ("repeat", _) => Wrap::Repeat,
+                    ("clamp", _) => Wrap::Clamp,
+                    ("mirror", _) => Wrap::Mirror,
+                    (wrap, position) => {
+                        return Err(SceneError::new(
+                            position,
+                            format!(
+                                "unknown wrap mode `{}`, expected `repeat`, `clamp` or `mirror`",
+                                wrap
+                            ),
+                        ))
+                    }
+                };
+                let texture = ImageTexture::load(&path, filter, wrap).map_err(|e| {
+                    SceneError::new(
+                        block.position,
+                        format!("cannot load {}: {}", path.display(), e),
+                    )
+                })?;
+                Arc::new(texture)
+            }
+            kind => {
+                return Err(SceneError::new(
+                    block.position,
+                    format!("unknown texture kind `{}`", kind),
+                ))
+            }
+        };
+        fields.finish()?;
+        Ok(texture)
+    }
+
+    /// A material is either a name or a block
+    fn material(&self, value: &Value, position: Position) -> Result<Arc<dyn Material>> {
+        let block = match value {
+            Value::Ident(name) => {
+                return self.materials.get(name).cloned().ok_or_else(|| {
+                    SceneError::new(position, format!("unknown material `{}`", name))
+                })
+            }
+            Value::Block(block) => block,
+            _ => return mismatch("a material", value, position),
+        };
+
+        let mut fields = Fields::new(block);
+        let material: Arc<dyn Material> = match block.kind.as_str() {
+            "lambertian" => {
+                let (albedo, albedo_position) = fields.require("albedo")?;
+                Arc::new(Lambertian::new(self.texture(albedo, albedo_position)?))
+            }
+            "metal" => Arc::new(Metal::new(
+                fields.vector("albedo")?,
+                fields.number_or("fuzz", 0.0)?,
+            )),
+            "dielectric" => Arc::new(Dielectric::new(fields.number("ref_idx")?)),
+            "diffuse_light" => {
+                let (emit, emit_position) = fields.require("emit")?;
+                Arc::new(DiffuseLight::new(self.texture(emit, emit_position)?))
+            }
+            "isotropic" => {
+                let (albedo, albedo_position) = fields.require("albedo")?;
+                Arc::new(Isotropic::new(self.texture(albedo, albedo_position)?))
+            }
+            kind => {
+                return Err(SceneError::new(
+                    block.position,
+                    format!("unknown material kind `{}`", kind),
+                ))
+            }
+        };
+        fields.finish()?;
+        Ok(material)
+    }
+
+    /// Meshes of an OBJ file, faces without material getting the one of the block if any
+    fn obj(&self, block: &Block) -> Result<Vec<Arc<dyn Hittable>>> {
+        let mut fields = Fields::new(block);
+        let path = self.directory.join(fields.string("path")?);
+        let default_material = match fields.get("material") {
+            Some((material, position)) => self.material(material, position)?,
+            None => default_material(),
+        };
+        fields.finish()?;
+
+        let meshes = obj::load(&path, default_material)
+            .map_err(|e| SceneError::new(block.position, e.to_string()))?;
+        Ok(meshes
+            .into_iter()
+            .map(|mesh| Arc::new(mesh) as Arc<dyn Hittable>)
+            .collect())
+    }
+
+    /// An object nested in the field of another one
+    fn object(&self, value: &Value, position: Position) -> Result<Arc<dyn Hittable>> {
+        match value {
+            Value::Block(block) => self.hittable(block),
+            _ => mismatch("an object", value, position),
+        }
+    }
+
+    fn hittable(&self, block: &Block) -> Result<Arc<dyn Hittable>> {
+        let mut fields = Fields::new(block);
+        let hittable: Arc<dyn Hittable> = match block.kind.as_str() {
+            "sphere" => {
+                let center = fields.vector("center")?;
+                let radius = fields.number("radius")?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(Sphere::new(
+                    center,
+                    radius,
+                    self.material(material, position)?,
+                ))
+            }
+            "moving_sphere" => {
+                let initial_center = fields.vector("center0")?;
+                let final_center = fields.vector("center1")?;
+                let initial_time = fields.number_or("time0", 0.0)?;
+                let final_time = fields.number_or("time1", 1.0)?;
+                let radius = fields.number("radius")?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(MovingSphere::new(
+                    initial_center,
+                    final_center,
+                    initial_time,
+                    final_time,
+                    radius,
+                    self.material(material, position)?,
+                ))
+            }
+            "triangle" => {
+                let v0 = fields.vector("v0")?;
+                let v1 = fields.vector("v1")?;
+                let v2 = fields.vector("v2")?;
+                let normals = fields.vectors_or_empty("normals")?;
+                let uvs = fields.pairs_or_empty("uvs")?;
+                let (material, position) = fields.require("material")?;
+                let mut triangle = Triangle::new(v0, v1, v2, self.material(material, position)?);
+                match normals.as_slice() {
+                    [] => {}
+                    [n0, n1, n2] => triangle = triangle.with_normals([*n0, *n1, *n2]),
+                    _ => {
+                        return Err(SceneError::new(
+                            block.position,
+                            "a triangle must have exactly 3 normals",
+                        ))
+                    }
+                }
+                match uvs.as_slice() {
+                    [] => {}
+                    [uv0, uv1, uv2] => triangle = triangle.with_uvs([*uv0, *uv1, *uv2]),
+                    _ => {
+                        return Err(SceneError::new(
+                            block.position,
+                            "a triangle must have exactly 3 texture coordinates",
+                        ))
+                    }
+                }
+                Arc::new(triangle)
+            }
+            "mesh" => {
+                let positions = fields.vectors("positions")?;
+                let normals = fields.vectors_or_empty("normals")?;
+                if !normals.is_empty() && normals.len() != positions.len() {
+                    return Err(SceneError::new(
+                        block.position,
+                        "a mesh must have as many normals as positions",
+                    ));
+                }
+                let uvs = fields.pairs_or_empty("uvs")?;
+                if !uvs.is_empty() && uvs.len() != positions.len() {
+                    return Err(SceneError::new(
+                        block.position,
+                        "a mesh must have as many texture coordinates as positions",
+                    ));
+                }
+                let (triangles, triangles_position) = fields.require("triangles")?;
+                let indices = as_indices(triangles, triangles_position, positions.len())?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(TriangleMesh::new(
+                    positions,
+                    normals,
+                    uvs,
+                    indices,
+                    self.material(material, position)?,
+                ))
+            }
+            "xy_rect" => {
+                let (x0, x1) = (fields.number("x0")?, fields.number("x1")?);
+                let (y0, y1) = (fields.number("y0")?, fields.number("y1")?);
+                let k = fields.number("z")?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(XYRect::new(
+                    x0,
+                    x1,
+                    y0,
+                    y1,
+                    k,
+                    self.material(material, position)?,
+                ))
+            }
+            "xz_rect" => {
+                let (x0, x1) = (fields.number("x0")?, fields.number("x1")?);
+                let (z0, z1) = (fields.number("z0")?, fields.number("z1")?);
+                let k = fields.number("y")?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(XZRect::new(
+                    x0,
+                    x1,
+                    z0,
+                    z1,
+                    k,
+                    self.material(material, position)?,
+                ))
+            }
+            "yz_rect" => {
+                let (y0, y1) = (fields.number("y0")?, fields.number("y1")?);
+                let (z0, z1) = (fields.number("z0")?, fields.number("z1")?);
+                let k = fields.number("x")?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(YZRect::new(
+                    y0,
+                    y1,
+                    z0,
+                    z1,
+                    k,
+                    self.material(material, position)?,
+                ))
+            }
+            "box" => {
+                let min = fields.vector("min")?;
+                let max = fields.vector("max")?;
+                let (material, position) = fields.require("material")?;
+                Arc::new(rect::Box::new(min, max, self.material(material, position)?))
+            }
+            "flip" => {
+                let (object, position) = fields.require("object")?;
+                Arc::new(FlipNormals::new(self.object(object, position)?))
+            }
+            "constant_medium" => {
+                let (boundary, position) = fields.require("boundary")?;
+                let boundary = self.object(boundary, position)?;
+                let density = fields.number("density")?;
+                if density <= 0.0 {
+                    return Err(SceneError::new(
+                        block.position,
+                        "the density of a medium must be positive",
+                    ));
+                }
+                let (albedo, albedo_position) = fields.require("albedo")?;
+                Arc::new(ConstantMedium::new(
+                    boundary,
+                    density,
+                    self.texture(albedo, albedo_position)?,
+                ))
+            }
+            "transform" => {
+                let (object, position) = fields.require("object")?;
+                let object = self.object(object, position)?;
+                let scale = match fields.get("scale") {
+                    Some((Value::Number(factor), _)) => Vec3::new(*factor, *factor, *factor),
+                    Some((value, position)) => as_vector(value, position)?,
+                    None => Vec3::new(1.0, 1.0, 1.0),
+                };
+                let rotate = fields.vector_or("rotate", Vec3::new(0.0, 0.0, 0.0))?;
+                let translate = fields.vector_or("translate", Vec3::new(0.0, 0.0, 0.0))?;
+                let matrix = Matrix4::translation(translate)
+                    * Matrix4::rotation_z(rotate.z())
+                    * Matrix4::rotation_y(rotate.y())
+                    * Matrix4::rotation_x(rotate.x())
+                    * Matrix4::scaling(scale);
+                Arc::new(Transformed::new(object, matrix).ok_or_else(|| {
+                    SceneError::new(block.position, "the transformation cannot be inverted")
+                })?)
+            }
+            kind => {
+                return Err(SceneError::new(
+                    block.position,
+                    format!("unknown object `{}`", kind),
+                ))
+            }
+        };
+        fields.finish()?;
+        Ok(hittable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn parse(source: &str) -> Result<SceneFile> {
+        SceneFile::parse(source, Path::new(""))
+    }
+
+    fn error(source: &str) -> String {
+        parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn small_scene() {
+        let source = "
+            # A light above a ball
+            camera { look_from = (0, 0, 5), look_at = (0, 0, 0), vfov = 40 }
+            background = (0.1, 0.1, 0.1)
+
+            texture white = (1, 1, 1)
+            material lamp = diffuse_light { emit = white }
+            material ball = lambertian {
+                albedo = checker { odd = white, even = (0, 0, 0) }
+            }
+
+            sphere { center = (0, 0, 0), radius = 1, material = ball }
+            xz_rect { x0 = -1, x1 = 1, z0 = -1, z1 = 1, y = 3, material = lamp }
+        ";
+        let SceneFile { scene, camera } = parse(source).unwrap();
+
+        assert_eq!(camera.look_from.z(), 5.0);
+        assert_eq!((camera.look_at - camera.look_from).length(), 5.0);
+        assert_eq!(camera.vfov, 40.0);
+        assert_eq!(scene.hittables.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+
+        let direction = (camera.look_at - camera.look_from).unit_vector();
+        let ray = Ray::new(camera.look_from, direction, 0.0);
+        let record = scene.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-4);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let record = scene.hit(&ray, 1.5, f32::MAX).unwrap();
+        assert!((record.t - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn unknown_name() {
+        let source = "sphere { center = (0, 0, 0), radius = 1, material = steel }";
+        assert_eq!(error(source), "1:53: unknown material `steel`");
+        let source = "material red = lambertian {\n    albedo = rust\n}";
+        assert_eq!(error(source), "2:14: unknown texture `rust`");
+    }
+
+    #[test]
+    fn missing_field() {
+        let source = "texture white = (1, 1, 1)
+sphere { center = (0, 0, 0), material = lambertian { albedo = white } }";
+        assert_eq!(error(source), "2:1: missing field `radius` in `sphere`");
+    }
+
+    #[test]
+    fn wrong_type() {
+        let source = "sphere { center = 1, radius = 1 }";
+        assert_eq!(error(source), "1:19: expected a vector, found a number");
+    }
+
+    #[test]
+    fn unterminated_block() {
+        let source = "camera {\n    look_from = (0, 0, 5)\n";
+        assert_eq!(error(source), "1:1: unterminated `camera` block");
+        let source =
+            "box { min = (0, 0, 0), max = (1, 1, 1), material = lambertian { albedo = (1, 1, 1) }";
+        assert_eq!(error(source), "1:1: unterminated `box` block");
+    }
+}
@@ -1,11 +1,21 @@
+use std::f32::consts::{FRAC_PI_2, PI};
 use std::sync::Arc;
 
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{self, HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
+/// Texture coordinates of a point of the unit sphere, `u` going around the y axis from -x
+/// and `v` going from the bottom to the top
+pub fn sphere_uv(p: &Vec3) -> (f32, f32) {
+    let phi = p.z().atan2(p.x());
+    let theta = p.y().clamp(-1.0, 1.0).asin();
+    (1.0 - (phi + PI) / (2.0 * PI), (theta + FRAC_PI_2) / PI)
+}
+
 #[derive(Debug)]
 pub struct Sphere {
     pub center: Vec3,
@@ -34,10 +44,14 @@ impl Hittable for Sphere {
             let mut temp = (-b - discriminant.sqrt()) / (2.0 * a);
             if temp < t_max && temp > t_min {
                 let p = ray.point_at_parameter(temp);
+                let normal = (p - self.center) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 let rec = HitRecord {
                     t: temp,
                     p,
-                    normal: (p - self.center) / self.radius,
+                    normal,
+                    u,
+                    v,
                     material: self.material.clone(),
                 };
                 return Some(rec);
@@ -45,10 +59,14 @@ impl Hittable for Sphere {
             temp = (-b + discriminant.sqrt()) / (2.0 * a);
             if temp < t_max && temp > t_min {
                 let p = ray.point_at_parameter(temp);
+                let normal = (p - self.center) / self.radius;
+                let (u, v) = sphere_uv(&normal);
                 let rec = HitRecord {
                     t: temp,
                     p,
-                    normal: (p - self.center) / self.radius,
+                    normal,
+                    u,
+                    v,
                     material: self.material.clone(),
                 };
                 return Some(rec);
@@ -60,8 +78,51 @@ impl Hittable for Sphere {
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         let aabb = AABB::new(
             self.center - Vec3::new(self.radius, self.radius, self.radius),
-            self.center - Vec3::new(self.radius, self.radius, self.radius),
+            self.center + Vec3::new(self.radius, self.radius, self.radius),
         );
         Some(aabb)
     }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    /// Uniform in the cone of the directions hitting the sphere, or on the whole sphere when
+    /// seen from inside
+    fn sample_point(&self, origin: &Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let to_center = self.center - *origin;
+        let distance_squared = to_center.squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return Some(self.center + Vec3::random_unit_vector(sampler) * self.radius);
+        }
+
+        let cos_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let (u, v) = sampler.get_2d();
+        let z = 1.0 + u * (cos_max - 1.0);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let (sin, cos) = (2.0 * PI * v).sin_cos();
+        let w = to_center.unit_vector();
+        let (a, b) = w.basis();
+        let direction = a * (r * cos) + b * (r * sin) + w * z;
+        // Rounding can make the directions at the edge of the cone miss it
+        self.hit(&Ray::new(*origin, direction, time), 0.0, f32::MAX)
+            .map(|rec| rec.p)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        let rec = match self.hit(&Ray::new(*origin, *direction, time), 0.001, f32::MAX) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+        let distance_squared = (self.center - *origin).squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            let area = 4.0 * PI * radius_squared;
+            return hittable::area_to_solid_angle_pdf(direction, rec.t, &rec.normal, area);
+        }
+
+        let cos_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
 }
@@ -1,5 +1,6 @@
-use ::std::fmt::Debug;
-use ::std::sync::Arc;
+use ::std::fmt::{self, Debug};
+use ::std::path::Path;
+use ::std::sync::{Arc, OnceLock};
 
 use crate::perlin::Perlin;
 use crate::vec3::Vec3;
@@ -76,3 +77,140 @@ impl Texture for NoiseTexture {
             * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(p, 7)).sin())
     }
 }
+
+/// How texels are interpolated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+/// What happens to texture coordinates outside of `[0, 1]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    fn apply(self, i: i64, len: usize) -> usize {
+        let len = len as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(len),
+            Wrap::Clamp => i.clamp(0, len - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * len);
+                if i >= len {
+                    2 * len - 1 - i
+                } else {
+                    i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+/// Texture mapped with the texture coordinates, `(0, 0)` being the bottom left corner
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// Linear colors, row by row from the top
+    texels: Vec<Vec3>,
+    filter: Filter,
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3>, filter: Filter, wrap: Wrap) -> Self {
+        assert_eq!(texels.len(), width * height);
+        assert!(width > 0 && height > 0);
+        Self {
+            width,
+            height,
+            texels,
+            filter,
+            wrap,
+        }
+    }
+
+    /// Load any format supported by the `image` crate, the image being sRGB encoded
+    pub fn load<P: AsRef<Path>>(path: P, filter: Filter, wrap: Wrap) -> image::ImageResult<Self> {
+        let image = image::open(path)?.to_rgb();
+        let (width, height) = image.dimensions();
+        let texels = image
+            .pixels()
+            .map(|pixel| {
+                Vec3::new(
+                    srgb_to_linear(pixel[0]),
+                    srgb_to_linear(pixel[1]),
+                    srgb_to_linear(pixel[2]),
+                )
+            })
+            .collect();
+        Ok(Self::new(
+            width as usize,
+            height as usize,
+            texels,
+            filter,
+            wrap,
+        ))
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Vec3 {
+        let i = self.wrap.apply(i, self.width);
+        let j = self.wrap.apply(j, self.height);
+        self.texels[i + j * self.width]
+    }
+}
+
+impl Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("filter", &self.filter)
+            .field("wrap", &self.wrap)
+            .finish()
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vec3) -> Vec3 {
+        // Texel space, with the rows going down
+        let x = u * self.width as f32;
+        let y = (1.0 - v) * self.height as f32;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // Texel centers are at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (i, j) = (x.floor(), y.floor());
+                let (fx, fy) = (x - i, y - j);
+                let (i, j) = (i as i64, j as i64);
+                let top = self.texel(i, j) * (1.0 - fx) + self.texel(i + 1, j) * fx;
+                let bottom = self.texel(i, j + 1) * (1.0 - fx) + self.texel(i + 1, j + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+/// Decode an 8 bits sRGB channel to linear
+pub fn srgb_to_linear(value: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for (i, linear) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *linear = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        table
+    })[value as usize]
+}
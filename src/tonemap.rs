@@ -0,0 +1,272 @@
+//! Conversion of the linear radiance of the film to 8-bit sRGB, for the window and the
+//! usual image formats. It is cheap enough to be applied again whenever a setting changes.
+
+use ::std::fmt;
+use ::std::str::FromStr;
+
+use crate::film::Film;
+use crate::random;
+use crate::vec3::Vec3;
+
+/// Rec. 709 weights of the channels in the luminance
+const LUMINANCE: Vec3 = Vec3::new(0.2126, 0.7152, 0.0722);
+
+/// Curve compressing the exposed radiance into `[0, 1]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Operator {
+    /// Everything above 1 is clipped
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on the luminance, never reaching white
+    Reinhard,
+    /// Reinhard reaching white at the luminance `white`
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// Hable's filmic curve from Uncharted 2
+    Hable,
+}
+
+impl Operator {
+    const ALL: [Operator; 5] = [
+        Operator::Clamp,
+        Operator::Reinhard,
+        Operator::ExtendedReinhard,
+        Operator::Aces,
+        Operator::Hable,
+    ];
+
+    /// The following one, wrapping around, to cycle through them in the window
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&o| o == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl FromStr for Operator {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "clamp" | "none" => Ok(Operator::Clamp),
+            "reinhard" => Ok(Operator::Reinhard),
+            "extended-reinhard" | "reinhard-extended" => Ok(Operator::ExtendedReinhard),
+            "aces" => Ok(Operator::Aces),
+            "hable" | "uncharted" | "uncharted2" => Ok(Operator::Hable),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Operator::Clamp => "clamp",
+            Operator::Reinhard => "reinhard",
+            Operator::ExtendedReinhard => "extended-reinhard",
+            Operator::Aces => "aces",
+            Operator::Hable => "hable",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Everything between the film and the 8-bit pixels
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMap {
+    /// In stops, each one doubling the radiance
+    pub exposure: f32,
+    pub operator: Operator,
+    /// Luminance mapped to white by the extended Reinhard operator
+    pub white: f32,
+    /// Add a triangular noise of one step before the quantization, hiding the banding
+    pub dither: bool,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            operator: Operator::default(),
+            white: 4.0,
+            dither: false,
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {:+.1} EV", self.operator, self.exposure)?;
+        if self.dither {
+            write!(f, ", dithered")?;
+        }
+        Ok(())
+    }
+}
+
+impl ToneMap {
+    /// sRGB pixels of the film, row by row from the top
+    pub fn apply(&self, film: &Film) -> Vec<[u8; 3]> {
+        film.colors()
+            .enumerate()
+            .map(|(index, color)| self.pixel(color, index as u64))
+            .collect()
+    }
+
+    /// `index` only seeds the dithering, so that it does not change between two applications
+    fn pixel(&self, color: Vec3, index: u64) -> [u8; 3] {
+        let scale = self.exposure.exp2();
+        // Also removes the NaNs
+        let color = Vec3::new(
+            (color.r() * scale).max(0.0),
+            (color.g() * scale).max(0.0),
+            (color.b() * scale).max(0.0),
+        );
+        let mapped = self.map(color);
+
+        let mut pixel = [0; 3];
+        for (channel, value) in pixel.iter_mut().enumerate() {
+            let encoded = srgb_oetf(mapped[channel].clamp(0.0, 1.0)) * 255.0;
+            let noise = if self.dither {
+                let hash = random::hash(&[index, channel as u64]);
+                // Sum of two uniform numbers, triangular in (-1, 1)
+                random::linear_from_bits(hash) + random::linear_from_bits(hash << 32) - 1.0
+            } else {
+                0.0
+            };
+            *value = (encoded + noise + 0.5).clamp(0.0, 255.0) as u8;
+        }
+        pixel
+    }
+
+    fn map(&self, color: Vec3) -> Vec3 {
+        match self.operator {
+            Operator::Clamp => color,
+            Operator::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            Operator::ExtendedReinhard => {
+                let white_squared = self.white * self.white;
+                scale_luminance(color, |l| l * (1.0 + l / white_squared) / (1.0 + l))
+            }
+            // Clamped as in the original fit, whose curve goes slightly above 1
+            Operator::Aces => per_channel(color, |x| {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).min(1.0)
+            }),
+            Operator::Hable => {
+                // Exposure bias and linear white point of the original curve
+                const BIAS: f32 = 2.0;
+                const WHITE: f32 = 11.2;
+                let white_scale = 1.0 / hable(WHITE);
+                per_channel(color, |x| hable(BIAS * x) * white_scale)
+            }
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15; // Shoulder strength
+    const B: f32 = 0.50; // Linear strength
+    const C: f32 = 0.10; // Linear angle
+    const D: f32 = 0.20; // Toe strength
+    const E: f32 = 0.02; // Toe numerator
+    const F: f32 = 0.30; // Toe denominator
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn per_channel<F: Fn(f32) -> f32>(color: Vec3, curve: F) -> Vec3 {
+    Vec3::new(curve(color.r()), curve(color.g()), curve(color.b()))
+}
+
+/// Apply `curve` to the luminance only, keeping the hue and the saturation
+fn scale_luminance<F: Fn(f32) -> f32>(color: Vec3, curve: F) -> Vec3 {
+    let luminance = color.dot(&LUMINANCE);
+    if luminance > 0.0 {
+        color * (curve(luminance) / luminance)
+    } else {
+        color
+    }
+}
+
+/// Encoding of a linear value in `[0, 1]` for an sRGB display
+fn srgb_oetf(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone_map(operator: Operator) -> ToneMap {
+        ToneMap {
+            operator,
+            ..ToneMap::default()
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let mut operator = Operator::default();
+        let mut seen = Vec::new();
+        for _ in 0..Operator::ALL.len() {
+            seen.push(operator);
+            operator = operator.next();
+        }
+        assert_eq!(operator, Operator::default());
+        assert!(Operator::ALL.iter().all(|o| seen.contains(o)));
+    }
+
+    #[test]
+    fn srgb() {
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        // The linear segment meets the power curve
+        let threshold = 0.003_130_8f32;
+        let above = f32::from_bits(threshold.to_bits() + 1);
+        assert!((srgb_oetf(threshold) - srgb_oetf(above)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bounded() {
+        for &x in [1.0, 10.0, 1e3, 1e6, 1e20].iter() {
+            let gray = Vec3::new(x, x, x);
+            let color = Vec3::new(x, x / 2.0, x / 10.0);
+            let reinhard = tone_map(Operator::Reinhard);
+            assert!(reinhard.map(gray).max_component() <= 1.0);
+            assert!(reinhard.map(color).dot(&LUMINANCE) <= 1.0);
+            for &color in [gray, color].iter() {
+                let mapped = tone_map(Operator::Aces).map(color);
+                for channel in 0..3 {
+                    assert!((0.0..=1.0).contains(&mapped[channel]), "{:?}", mapped);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extended_reinhard_white() {
+        let tone_map = tone_map(Operator::ExtendedReinhard);
+        let white = tone_map.white;
+        let mapped = tone_map.map(Vec3::new(white, white, white));
+        for channel in 0..3 {
+            assert!((mapped[channel] - 1.0).abs() < 1e-6, "{:?}", mapped);
+        }
+    }
+
+    #[test]
+    fn deterministic_dithering() {
+        let tone_map = ToneMap {
+            dither: true,
+            ..ToneMap::default()
+        };
+        let color = Vec3::new(0.2, 0.5, 0.8);
+        for index in 0..64 {
+            assert_eq!(tone_map.pixel(color, index), tone_map.pixel(color, index));
+        }
+        // The noise changes from one pixel to the other
+        let pixels: Vec<_> = (0..64).map(|index| tone_map.pixel(color, index)).collect();
+        assert!(pixels.iter().any(|pixel| *pixel != pixels[0]));
+    }
+}
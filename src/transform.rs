@@ -0,0 +1,169 @@
+use ::std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+/// Instance of a hittable placed in the world by an affine transformation, so the same
+/// object can be shared between several instances
+#[derive(Debug)]
+pub struct Transformed {
+    hittable: Arc<dyn Hittable>,
+    /// From object space to world space
+    matrix: Matrix4,
+    /// From world space to object space
+    inverse: Matrix4,
+    /// Transforms the normals from object space to world space
+    normal_matrix: Matrix4,
+}
+
+impl Transformed {
+    /// `None` if `matrix` cannot be inverted
+    pub fn new(hittable: Arc<dyn Hittable>, matrix: Matrix4) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+        Some(Self {
+            hittable,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        })
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // The direction is not normalized, so distances are the same in both spaces
+        let object_ray = Ray::new(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
+            ray.time(),
+        )
+        .with_sample(ray.sample());
+        let rec = self.hittable.hit(&object_ray, t_min, t_max)?;
+        Some(HitRecord {
+            p: ray.point_at_parameter(rec.t),
+            normal: self
+                .normal_matrix
+                .transform_vector(rec.normal)
+                .unit_vector(),
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let aabb = self.hittable.bounding_box(t0, t1)?;
+        let (min, max) = (aabb.min(), aabb.max());
+        let corner = |i: usize| {
+            let pick = |a: usize| if i & (1 << a) == 0 { min[a] } else { max[a] };
+            self.matrix
+                .transform_point(Vec3::new(pick(0), pick(1), pick(2)))
+        };
+
+        let first = corner(0);
+        let (min, max) = (1..8)
+            .map(corner)
+            .fold((first, first), |(mut min, mut max), p| {
+                for a in 0..3 {
+                    min[a] = min[a].min(p[a]);
+                    max[a] = max[a].max(p[a]);
+                }
+                (min, max)
+            });
+        Some(AABB::new(min, max))
+    }
+
+    fn is_light(&self) -> bool {
+        self.hittable.is_light()
+    }
+
+    fn sample_point(&self, origin: &Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let object_origin = self.inverse.transform_point(*origin);
+        let point = self.hittable.sample_point(&object_origin, time, sampler)?;
+        Some(self.matrix.transform_point(point))
+    }
+
+    /// Solid angles are not kept by scalings, the density is multiplied by the Jacobian of
+    /// the mapping of the directions `w -> L w / |L w|`, which is `|det L| / |L w|^3`
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, time: f32) -> f32 {
+        let direction = direction.unit_vector();
+        let object_direction = self.inverse.transform_vector(direction);
+        let length = object_direction.length();
+        let pdf = self.hittable.pdf_value(
+            &self.inverse.transform_point(*origin),
+            &(object_direction / length),
+            time,
+        );
+        pdf * self.inverse.linear_determinant().abs() / (length * length * length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::rect::{self, XYRect};
+    use crate::texture::ConstantTexture;
+
+    fn white() -> Arc<dyn Material> {
+        let texture = ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0));
+        Arc::new(Lambertian::new(Arc::new(texture)))
+    }
+
+    #[test]
+    fn normal_under_non_uniform_scale() {
+        let matrix = Matrix4::scaling(Vec3::new(3.0, 1.0, 0.5))
+            * Matrix4::rotation_y(30.0)
+            * Matrix4::rotation_x(20.0);
+        let rect = XYRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, white());
+        let transformed = Transformed::new(Arc::new(rect), matrix).unwrap();
+
+        let target = matrix.transform_point(Vec3::new(0.2, -0.3, 0.0));
+        let origin = Vec3::new(1.0, 2.0, 10.0);
+        let rec = transformed
+            .hit(&Ray::new(origin, target - origin, 0.0), 0.001, f32::MAX)
+            .unwrap();
+        assert!((rec.p - target).length() < 1e-4);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-5);
+        // Still perpendicular to the transformed sides of the rectangle
+        for side in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)].iter() {
+            let tangent = matrix.transform_vector(*side).unit_vector();
+            assert!(rec.normal.dot(&tangent).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn translated_box() {
+        let offset = Vec3::new(2.0, 3.0, 4.0);
+        let unit = rect::Box::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), white());
+        let translated = Transformed::new(Arc::new(unit), Matrix4::translation(offset)).unwrap();
+        let in_place = rect::Box::new(offset, offset + Vec3::new(1.0, 1.0, 1.0), white());
+
+        let rays = [
+            (Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.5, 3.5, 4.5)),
+            (Vec3::new(2.5, 10.0, 4.2), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(-5.0, 3.1, 4.9), Vec3::new(1.0, 0.05, -0.02)),
+            (Vec3::new(2.5, 3.5, 4.5), Vec3::new(0.3, 0.2, 1.0)),
+            (Vec3::new(0.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+        ];
+        for (origin, direction) in rays.iter() {
+            let ray = Ray::new(*origin, *direction, 0.0);
+            match (
+                translated.hit(&ray, 0.001, f32::MAX),
+                in_place.hit(&ray, 0.001, f32::MAX),
+            ) {
+                (Some(found), Some(expected)) => {
+                    assert!((found.t - expected.t).abs() < 1e-4);
+                    assert!((found.p - expected.p).length() < 1e-4);
+                    assert!((found.normal - expected.normal).length() < 1e-5);
+                    assert!((found.u - expected.u).abs() < 1e-4);
+                    assert!((found.v - expected.v).abs() < 1e-4);
+                }
+                (None, None) => {}
+                _ => panic!("{:?} {:?}", origin, direction),
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use ::std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{self, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+/// Thickness given to the bounding box of a triangle lying in an axis-aligned plane
+const PADDING: f32 = 1e-4;
+
+#[derive(Debug)]
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f32, f32); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    /// Shading normals, interpolated between the vertices
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Texture coordinates of each vertex
+    pub fn with_uvs(mut self, uvs: [(f32, f32); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+
+    /// Texture coordinates at the barycentric coordinates `(b1, b2)`, which are the
+    /// barycentric coordinates themselves without per-vertex texture coordinates
+    pub fn uv(&self, b1: f32, b2: f32) -> (f32, f32) {
+        match &self.uvs {
+            Some(uvs) => interpolate_uv(uvs, b1, b2),
+            None => (b1, b2),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [v0, v1, v2] = self.vertices;
+        let (t, b1, b2) = intersect(v0, v1, v2, ray, t_min, t_max)?;
+        let normal = match &self.normals {
+            Some(normals) => interpolate(normals, b1, b2).unit_vector(),
+            None => (v1 - v0).cross(&(v2 - v0)).unit_vector(),
+        };
+        let (u, v) = self.uv(b1, b2);
+        Some(HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            normal,
+            u,
+            v,
+            material: self.material.clone(),
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(&self.vertices))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(sample(&self.vertices, sampler))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, _time: f32) -> f32 {
+        pdf(&self.vertices, origin, direction, area(&self.vertices))
+    }
+}
+
+/// Uniform point of the triangle
+pub fn sample(vertices: &[Vec3; 3], sampler: &mut dyn Sampler) -> Vec3 {
+    // Uniform barycentric coordinates, from the square root warping
+    let (u, v) = sampler.get_2d();
+    let root = u.sqrt();
+    interpolate(vertices, root * (1.0 - v), root * v)
+}
+
+/// Density with respect to the solid angle of a point of the triangle seen in `direction`
+/// from `origin`, the points being picked uniformly on a surface of area `area` which holds
+/// the triangle
+pub fn pdf(vertices: &[Vec3; 3], origin: &Vec3, direction: &Vec3, area: f32) -> f32 {
+    let [v0, v1, v2] = *vertices;
+    let ray = Ray::new(*origin, *direction, 0.0);
+    match intersect(v0, v1, v2, &ray, 0.001, f32::MAX) {
+        Some((t, _, _)) => {
+            let normal = (v1 - v0).cross(&(v2 - v0)).unit_vector();
+            hittable::area_to_solid_angle_pdf(direction, t, &normal, area)
+        }
+        None => 0.0,
+    }
+}
+
+pub fn area(vertices: &[Vec3; 3]) -> f32 {
+    let [v0, v1, v2] = *vertices;
+    0.5 * (v1 - v0).cross(&(v2 - v0)).length()
+}
+
+/// Möller–Trumbore intersection, returning the distance and the barycentric coordinates
+/// `(b1, b2)` relative to `v1` and `v2`. Both faces can be hit.
+pub fn intersect(
+    v0: Vec3,
+    v1: Vec3,
+    v2: Vec3,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = ray.direction().cross(&edge2);
+    let det = edge1.dot(&p);
+    // Relative threshold, so that small and large triangles are treated alike
+    if det.abs() <= f32::EPSILON * edge1.length() * edge2.length() * ray.direction().length() {
+        return None;
+    }
+
+    let inv_det = det.recip();
+    let s = ray.origin() - v0;
+    let b1 = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let b2 = ray.direction().dot(&q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&q) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+pub fn interpolate(values: &[Vec3; 3], b1: f32, b2: f32) -> Vec3 {
+    values[0] * (1.0 - b1 - b2) + values[1] * b1 + values[2] * b2
+}
+
+pub fn interpolate_uv(uvs: &[(f32, f32); 3], b1: f32, b2: f32) -> (f32, f32) {
+    let b0 = 1.0 - b1 - b2;
+    (
+        uvs[0].0 * b0 + uvs[1].0 * b1 + uvs[2].0 * b2,
+        uvs[0].1 * b0 + uvs[1].1 * b1 + uvs[2].1 * b2,
+    )
+}
+
+pub fn bounding_box(vertices: &[Vec3; 3]) -> AABB {
+    let mut min = vertices[0];
+    let mut max = vertices[0];
+    for vertex in &vertices[1..] {
+        for a in 0..3 {
+            min[a] = min[a].min(vertex[a]);
+            max[a] = max[a].max(vertex[a]);
+        }
+    }
+    let padding = Vec3::new(PADDING, PADDING, PADDING);
+    AABB::new(min - padding, max + padding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::ConstantTexture;
+
+    fn triangle() -> Triangle {
+        let texture = ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0));
+        Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Arc::new(texture))),
+        )
+    }
+
+    fn down_at(x: f32, y: f32) -> Ray {
+        Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn edges() {
+        let triangle = triangle();
+        // On each edge and on a vertex
+        for &(x, y) in [(0.5, 0.0), (0.0, 0.25), (0.5, 0.5), (0.0, 0.0)].iter() {
+            let rec = triangle.hit(&down_at(x, y), 0.001, f32::MAX).unwrap();
+            assert_eq!(rec.t, 1.0);
+            assert_eq!((rec.u, rec.v), (x, y));
+        }
+        // Just outside
+        for &(x, y) in [(0.5, -1e-4), (-1e-4, 0.25), (0.5, 0.5001)].iter() {
+            assert!(triangle.hit(&down_at(x, y), 0.001, f32::MAX).is_none());
+        }
+    }
+
+    #[test]
+    fn parallel() {
+        let triangle = triangle();
+        let direction = Vec3::new(1.0, 1.0, 0.0);
+        for &z in [0.0, 0.5].iter() {
+            let ray = Ray::new(Vec3::new(-1.0, -0.8, z), direction, 0.0);
+            assert!(triangle.hit(&ray, 0.001, f32::MAX).is_none());
+        }
+    }
+
+    #[test]
+    fn backface() {
+        let triangle = triangle();
+        let front = triangle.hit(&down_at(0.2, 0.3), 0.001, f32::MAX).unwrap();
+        let ray = Ray::new(Vec3::new(0.2, 0.3, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let back = triangle.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert_eq!(back.t, 2.0);
+        assert!((back.p - front.p).length() < 1e-6);
+        // The geometric normal, whichever side is hit
+        assert!((back.normal - front.normal).length() < 1e-6);
+    }
+}
@@ -1,6 +1,7 @@
+use std::f32::consts::PI;
 use std::ops;
 
-use crate::random::random;
+use crate::sampler::Sampler;
 
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Vec3 {
@@ -44,7 +45,7 @@ impl Vec3 {
 
     #[inline]
     pub fn squared_length(&self) -> f32 {
-        self.dot(&self)
+        self.dot(self)
     }
 
     #[inline]
@@ -57,11 +58,6 @@ impl Vec3 {
         *self / self.length()
     }
 
-    #[inline]
-    pub fn make_unit_vector(&mut self) {
-        *self = self.unit_vector();
-    }
-
     #[inline]
     pub fn dot(&self, rhs: &Self) -> f32 {
         self.data[0] * rhs.data[0] + self.data[1] * rhs.data[1] + self.data[2] * rhs.data[2]
@@ -76,22 +72,46 @@ impl Vec3 {
         )
     }
 
-    pub fn random_in_unit_disk() -> Self {
-        loop {
-            let vec = Self::new(random(), random(), 0.0) * 2.0 - Self::new(1.0, 1.0, 0.0);
-            if vec.squared_length() < 1.0 {
-                break vec;
-            }
-        }
-    }
-
-    pub fn random_in_unit_sphere() -> Self {
-        loop {
-            let vec = Self::new(random(), random(), random()) * 2.0 - Self::new(1.0, 1.0, 1.0);
-            if vec.squared_length() < 1.0 {
-                break vec;
-            }
-        }
+    #[inline]
+    pub fn max_component(&self) -> f32 {
+        self.data[0].max(self.data[1]).max(self.data[2])
+    }
+
+    /// Uniform in the unit disk of the xy plane, from one 2D sample
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Self {
+        let (u, v) = sampler.get_2d();
+        let (sin, cos) = (2.0 * PI * v).sin_cos();
+        Self::new(cos, sin, 0.0) * u.sqrt()
+    }
+
+    /// Uniform on the unit sphere, from one 2D sample
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Self {
+        let (u, v) = sampler.get_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let (sin, cos) = (2.0 * PI * v).sin_cos();
+        Self::new(r * cos, r * sin, z)
+    }
+
+    /// Uniform in the unit ball, from a 2D sample for the direction and a 1D one for the
+    /// distance to the center
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Self {
+        Self::random_unit_vector(sampler) * sampler.get_1d().cbrt()
+    }
+
+    /// Two unit vectors making an orthonormal basis with this unit vector, from Duff et al.
+    pub fn basis(&self) -> (Self, Self) {
+        let sign = 1f32.copysign(self.z());
+        let a = -1.0 / (sign + self.z());
+        let b = self.x() * self.y() * a;
+        (
+            Self::new(
+                1.0 + sign * self.x() * self.x() * a,
+                sign * b,
+                -sign * self.x(),
+            ),
+            Self::new(b, sign + self.y() * self.y() * a, -self.y()),
+        )
     }
 
     pub fn reflect(&self, other: &Self) -> Self {
@@ -182,6 +202,7 @@ impl ops::MulAssign<f32> for Vec3 {
 
 impl ops::DivAssign<f32> for Vec3 {
     #[inline]
+    #[allow(clippy::suspicious_op_assign_impl)]
     fn div_assign(&mut self, rhs: f32) {
         let invert_rhs = rhs.recip(); // Save compute time, as division being expansive
 
@@ -254,6 +275,7 @@ impl ops::Div<f32> for Vec3 {
     type Output = Self;
 
     #[inline]
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, rhs: f32) -> Self::Output {
         let invert_rhs = rhs.recip(); // Save compute time, as division being expansive
 